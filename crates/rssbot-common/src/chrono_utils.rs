@@ -1,21 +1,242 @@
-use chrono::NaiveDateTime;
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime};
 
+/// Parse a date as found in feeds into UTC.
+///
+/// Besides RFC 2822 and RFC 3339 this accepts what feeds commonly get wrong: named and
+/// unknown zones, two-digit years, missing seconds, full or localized day names, ISO 8601
+/// dates without a zone or time, and Unix timestamps in seconds or milliseconds. Dates
+/// without a zone are taken as UTC.
 pub fn parse_datetime(s: &str) -> Option<NaiveDateTime> {
-    if let Ok(d) = chrono::DateTime::parse_from_rfc2822(s) {
+    let s = s.trim();
+    if s.is_empty() {
+        return None;
+    }
+
+    if let Ok(d) = DateTime::parse_from_rfc2822(s) {
         return Some(d.naive_utc());
     }
 
-    if let Ok(d) = chrono::DateTime::parse_from_rfc3339(s) {
+    if let Ok(d) = DateTime::parse_from_rfc3339(s) {
         return Some(d.naive_utc());
     }
 
-    if let Ok(d) = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S") {
-        return Some(d);
+    parse_timestamp(s)
+        .or_else(|| parse_iso8601(s))
+        .or_else(|| parse_rfc822(s))
+}
+
+fn parse_timestamp(s: &str) -> Option<NaiveDateTime> {
+    if !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let value = s.parse::<i64>().ok()?;
+    match s.len() {
+        9 | 10 => DateTime::from_timestamp(value, 0),
+        12 | 13 => DateTime::from_timestamp_millis(value),
+        _ => None,
+    }.map(|d| d.naive_utc())
+}
+
+const ISO8601_WITH_OFFSET: &[&str] = &[
+    "%Y-%m-%dT%H:%M:%S%.f%z",
+    "%Y-%m-%dT%H:%M%z",
+    "%Y-%m-%d %H:%M:%S%.f%z",
+    "%Y-%m-%d %H:%M:%S%.f %z",
+    "%Y-%m-%d %H:%M%z",
+    "%Y-%m-%d %H:%M %z",
+    "%Y%m%dT%H%M%S%z",
+];
+
+const ISO8601_NAIVE: &[&str] = &[
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%d %H:%M",
+    "%Y%m%dT%H%M%S",
+];
+
+fn parse_iso8601(s: &str) -> Option<NaiveDateTime> {
+    // `%z` does not take `Z`
+    let with_offset = match s.strip_suffix(['Z', 'z']) {
+        Some(rest) => format!("{}+00:00", rest.trim_end()),
+        None => s.to_string(),
+    };
+
+    for format in ISO8601_WITH_OFFSET {
+        if let Ok(d) = DateTime::parse_from_str(&with_offset, format) {
+            return Some(d.naive_utc());
+        }
+    }
+
+    for format in ISO8601_NAIVE {
+        if let Ok(d) = NaiveDateTime::parse_from_str(s, format) {
+            return Some(d);
+        }
+    }
+
+    // calendar dates and months only
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(&format!("{}-01", s), "%Y-%m-%d"))
+        .ok()
+        .map(|date| date.and_time(NaiveTime::MIN))
+}
+
+/// RFC 822 style dates, e.g. `Monday, 3 Jun 24 08:00 EST`.
+fn parse_rfc822(s: &str) -> Option<NaiveDateTime> {
+    // a trailing comment, e.g. `+0000 (UTC)`
+    let s = s.split('(').next()?.trim();
+
+    let mut tokens = s.split([' ', ',']).filter(|token| !token.is_empty()).peekable();
+
+    // the day name is redundant, and may be in any language
+    if tokens.peek()?.chars().all(|c| c.is_alphabetic() || c == '.') {
+        tokens.next();
+    }
+
+    let day = tokens.next()?.parse::<u32>().ok()?;
+    let month = month(tokens.next()?)?;
+    let year = match tokens.next()? {
+        year if year.len() == 2 => {
+            let year = year.parse::<i32>().ok()?;
+            if year < 50 { 2000 + year } else { 1900 + year }
+        }
+        year => year.parse::<i32>().ok()?,
+    };
+
+    let date = NaiveDate::from_ymd_opt(year, month, day)?;
+    let time = match tokens.next() {
+        Some(time) => NaiveTime::parse_from_str(time, "%H:%M:%S")
+            .or_else(|_| NaiveTime::parse_from_str(time, "%H:%M"))
+            .ok()?,
+        None => NaiveTime::MIN,
+    };
+
+    let offset = match tokens.next() {
+        Some(zone) => offset(zone)?,
+        None => FixedOffset::east_opt(0)?,
+    };
+
+    date.and_time(time)
+        .checked_sub_offset(offset)
+}
+
+fn month(name: &str) -> Option<u32> {
+    const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+
+    let prefix = name.get(..3)?.to_ascii_lowercase();
+    MONTHS.iter()
+        .position(|month| *month == prefix)
+        .map(|index| index as u32 + 1)
+}
+
+/// Numeric offsets and zone abbreviations; unknown abbreviations are UTC, as RFC 2822 asks.
+fn offset(zone: &str) -> Option<FixedOffset> {
+    let sign = match zone.chars().next()? {
+        '+' => Some(1),
+        '-' => Some(-1),
+        _ => None,
+    };
+
+    if let Some(sign) = sign {
+        let digits = zone[1..].replace(':', "");
+        if digits.len() != 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        let hours = digits[..2].parse::<i32>().ok()?;
+        let minutes = digits[2..].parse::<i32>().ok()?;
+        return FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60));
+    }
+
+    if !zone.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
     }
-    
-    if let Ok(d) = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.3f") {
-        return Some(d);
+
+    let hours = match zone.to_ascii_uppercase().as_str() {
+        "EST" => -5,
+        "EDT" => -4,
+        "CST" => -6,
+        "CDT" => -5,
+        "MST" => -7,
+        "MDT" => -6,
+        "PST" => -8,
+        "PDT" => -7,
+        "AKST" => -9,
+        "AKDT" => -8,
+        "HST" => -10,
+        "BST" | "CET" | "WEST" => 1,
+        "CEST" | "EET" => 2,
+        "EEST" | "MSK" => 3,
+        "HKT" | "SGT" | "AWST" => 8,
+        "JST" | "KST" => 9,
+        "AEST" => 10,
+        "AEDT" => 11,
+        "NZST" => 12,
+        "NZDT" => 13,
+        _ => 0,
+    };
+
+    FixedOffset::east_opt(hours * 3600)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Timelike;
+
+    use super::*;
+
+    #[test]
+    fn parses_lenient_formats() {
+        let expected = NaiveDate::from_ymd_opt(2024, 6, 3).unwrap().and_hms_opt(8, 0, 0).unwrap();
+
+        for s in [
+            "Mon, 03 Jun 2024 08:00:00 +0000",
+            "Mon, 03 Jun 2024 08:00:00 GMT",
+            "Mon, 03 Jun 2024 04:00:00 EDT",
+            "Mon, 03 Jun 2024 10:00:00 CEST",
+            "Mon, 03 Jun 2024 17:00:00 JST",
+            "Mon, 03 Jun 2024 08:00:00 XYZ",
+            "Mon, 3 Jun 2024 08:00 +0000",
+            "Mon, 03 Jun 24 08:00:00 +0000",
+            "Monday, 03 June 2024 08:00:00 +0000",
+            "Mo, 03 Jun 2024 10:00:00 +0200",
+            "lun., 03 Jun 2024 08:00:00 +0000",
+            "Mon 03 Jun 2024 08:00:00 +00:00",
+            "Mon, 03 Jun 2024 08:00:00 +0000 (UTC)",
+            "03 Jun 2024 08:00:00 +0000",
+            "2024-06-03T08:00:00Z",
+            "2024-06-03T10:00:00+02:00",
+            "2024-06-03T10:00:00+0200",
+            "2024-06-03T08:00:00.123Z",
+            "2024-06-03T08:00:00",
+            "2024-06-03T08:00",
+            "2024-06-03 08:00:00",
+            "2024-06-03 08:00:00.000",
+            "2024-06-03 10:00:00 +0200",
+            "20240603T080000Z",
+            " 2024-06-03T08:00:00Z\n",
+            "1717401600",
+            "1717401600000",
+        ] {
+            assert_eq!(parse_datetime(s).map(|d| d.with_nanosecond(0).unwrap()), Some(expected), "{:?}", s);
+        }
     }
 
-    None
-}
\ No newline at end of file
+    #[test]
+    fn parses_partial_dates_as_midnight() {
+        let midnight = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap().and_time(NaiveTime::MIN);
+
+        assert_eq!(parse_datetime("2024-06-03"), Some(midnight(2024, 6, 3)));
+        assert_eq!(parse_datetime("2024-06"), Some(midnight(2024, 6, 1)));
+        assert_eq!(parse_datetime("3 Jun 2024"), Some(midnight(2024, 6, 3)));
+        assert_eq!(parse_datetime("Mon, 03 Jun 99 00:00:00 GMT"), Some(midnight(1999, 6, 3)));
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        for s in ["", "   ", "yesterday", "Mon, 32 Jun 2024 08:00:00 GMT", "Mon, 03 Foo 2024 08:00:00 GMT", "12345", "2024-13-01"] {
+            assert_eq!(parse_datetime(s), None, "{:?}", s);
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::time::SystemTime;
+
+use opentelemetry::logs::{AnyValue, LogRecord as _, Logger as _, Severity};
+use opentelemetry::trace::{SpanContext, TraceContextExt, TraceFlags, TraceState};
+use opentelemetry_sdk::logs::{LogRecord, Logger, TraceContext};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_log::NormalizeEvent;
+use tracing_opentelemetry::OtelData;
+use tracing_subscriber::fmt::format::{Format, Json, Writer};
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::{LookupSpan, SpanRef};
+use tracing_subscriber::Layer;
+
+/// Forwards `tracing` events to an OpenTelemetry logger, tagged with the trace and span
+/// of the span they were emitted in.
+pub struct OtelLogLayer {
+    logger: Logger,
+}
+
+impl OtelLogLayer {
+    pub fn new(logger: Logger) -> Self {
+        Self { logger }
+    }
+}
+
+impl<S> Layer<S> for OtelLogLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        // events bridged from `log` carry their real target in their fields
+        let normalized = event.normalized_metadata();
+        let metadata = normalized.as_ref().unwrap_or_else(|| event.metadata());
+
+        let mut record = self.logger.create_log_record();
+        record.set_timestamp(SystemTime::now());
+        record.set_severity_number(severity(metadata.level()));
+        record.set_severity_text(Cow::Borrowed(metadata.level().as_str()));
+        record.add_attribute("target", metadata.target().to_string());
+
+        event.record(&mut RecordVisitor { record: &mut record });
+        record.trace_context = ctx.event_span(event).and_then(|span| trace_context(&span));
+
+        self.logger.emit(record);
+    }
+}
+
+struct RecordVisitor<'a> {
+    record: &'a mut LogRecord,
+}
+
+impl RecordVisitor<'_> {
+    fn record_value(&mut self, field: &Field, value: AnyValue) {
+        match field.name() {
+            "message" => self.record.set_body(value),
+            // fields added by the `log` bridge, already part of the metadata
+            name if name.starts_with("log.") => {}
+            name => self.record.add_attribute(name, value),
+        }
+    }
+}
+
+impl Visit for RecordVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.record_value(field, value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.record_value(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.record_value(field, i64::try_from(value).map_or_else(|_| value.to_string().into(), AnyValue::from));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.record_value(field, value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record_value(field, value.to_string().into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.record_value(field, format!("{:?}", value).into());
+    }
+}
+
+fn severity(level: &Level) -> Severity {
+    match *level {
+        Level::TRACE => Severity::Trace,
+        Level::DEBUG => Severity::Debug,
+        Level::INFO => Severity::Info,
+        Level::WARN => Severity::Warn,
+        Level::ERROR => Severity::Error,
+    }
+}
+
+/// Trace and span ID the OpenTelemetry layer assigned to a span, if it is traced.
+fn trace_context<S>(span: &SpanRef<'_, S>) -> Option<TraceContext>
+where
+    S: for<'a> LookupSpan<'a>,
+{
+    let extensions = span.extensions();
+    let data = extensions.get::<OtelData>()?;
+
+    // only root spans have their own trace ID, children inherit it from their parent
+    let trace_id = data.builder.trace_id
+        .unwrap_or_else(|| data.parent_cx.span().span_context().trace_id());
+    let span_id = data.builder.span_id?;
+
+    let span_context = SpanContext::new(trace_id, span_id, TraceFlags::default(), false, TraceState::default());
+    Some(TraceContext::from(&span_context))
+}
+
+/// JSON event format that adds `trace_id` and `span_id` to every line emitted in a span,
+/// so that logs can be correlated with traces.
+pub struct JsonWithTraceIds {
+    inner: Format<Json>,
+}
+
+impl JsonWithTraceIds {
+    pub fn new(inner: Format<Json>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S, N> FormatEvent<S, N> for JsonWithTraceIds
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        let Some(trace_context) = ctx.lookup_current().and_then(|span| trace_context(&span)) else {
+            return self.inner.format_event(ctx, writer, event);
+        };
+
+        let mut line = String::new();
+        self.inner.format_event(ctx, Writer::new(&mut line), event)?;
+
+        // splice the IDs in as the first keys of the object
+        match line.strip_prefix('{') {
+            Some(rest) => write!(
+                writer,
+                "{{\"trace_id\":\"{}\",\"span_id\":\"{}\",{}",
+                trace_context.trace_id,
+                trace_context.span_id,
+                rest,
+            ),
+            None => writer.write_str(&line),
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::sync::OnceLock;
+
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts,
+    Registry, TextEncoder,
+};
+
+/// Buckets for network round-trips, in seconds.
+const LATENCY_BUCKETS: [f64; 11] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 20.0, 30.0, 60.0];
+/// Buckets for a whole sync run, in seconds.
+const SYNC_BUCKETS: [f64; 10] = [1.0, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1200.0, 3600.0];
+
+/// Application metrics, exported in the Prometheus text format.
+pub struct Metrics {
+    registry: Registry,
+    /// Feed fetch duration, labelled by HTTP status code or `error` when no response arrived.
+    pub feed_fetch_duration: HistogramVec,
+    /// Feeds that could not be parsed, labelled by feed format.
+    pub feed_parse_errors: IntCounterVec,
+    /// Duration of a full sync of all subscriptions.
+    pub sync_duration: Histogram,
+    /// Messages delivered to Telegram.
+    pub messages_sent: IntCounter,
+    /// Failed delivery attempts, labelled by error kind and what happens to the message next.
+    pub messages_failed: IntCounterVec,
+    /// Deliveries waiting in the queue.
+    pub delivery_queue_depth: IntGauge,
+    /// Updates received from Telegram, labelled by update kind.
+    pub updates_received: IntCounterVec,
+    /// Errors returned by update handlers.
+    pub handler_errors: IntCounter,
+}
+
+impl Metrics {
+    fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new_custom(Some("rssbot".to_string()), None)?;
+
+        let feed_fetch_duration = HistogramVec::new(
+            HistogramOpts::new("feed_fetch_duration_seconds", "Feed fetch duration")
+                .buckets(LATENCY_BUCKETS.to_vec()),
+            &["status"],
+        )?;
+        let feed_parse_errors = IntCounterVec::new(
+            Opts::new("feed_parse_errors_total", "Feeds that could not be parsed"),
+            &["format"],
+        )?;
+        let sync_duration = Histogram::with_opts(
+            HistogramOpts::new("sync_duration_seconds", "Duration of a sync of all subscriptions")
+                .buckets(SYNC_BUCKETS.to_vec()),
+        )?;
+        let messages_sent = IntCounter::new("messages_sent_total", "Messages delivered to Telegram")?;
+        let messages_failed = IntCounterVec::new(
+            Opts::new("messages_failed_total", "Failed delivery attempts"),
+            &["kind", "outcome"],
+        )?;
+        let delivery_queue_depth = IntGauge::new("delivery_queue_depth", "Deliveries waiting in the queue")?;
+        let updates_received = IntCounterVec::new(
+            Opts::new("updates_received_total", "Updates received from Telegram"),
+            &["kind"],
+        )?;
+        let handler_errors = IntCounter::new("handler_errors_total", "Errors returned by update handlers")?;
+
+        registry.register(Box::new(feed_fetch_duration.clone()))?;
+        registry.register(Box::new(feed_parse_errors.clone()))?;
+        registry.register(Box::new(sync_duration.clone()))?;
+        registry.register(Box::new(messages_sent.clone()))?;
+        registry.register(Box::new(messages_failed.clone()))?;
+        registry.register(Box::new(delivery_queue_depth.clone()))?;
+        registry.register(Box::new(updates_received.clone()))?;
+        registry.register(Box::new(handler_errors.clone()))?;
+
+        Ok(Self {
+            registry,
+            feed_fetch_duration,
+            feed_parse_errors,
+            sync_duration,
+            messages_sent,
+            messages_failed,
+            delivery_queue_depth,
+            updates_received,
+            handler_errors,
+        })
+    }
+
+    /// Render all metrics in the Prometheus text exposition format.
+    pub fn encode(&self) -> String {
+        let mut buffer = Vec::new();
+        if let Err(err) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            tracing::error!("Failed to encode metrics: {}", err);
+        }
+
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+/// The process-wide metrics, registered on first use.
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(|| Metrics::new().expect("Failed to register metrics."))
+}
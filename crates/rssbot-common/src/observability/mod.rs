@@ -1,2 +1,4 @@
+pub mod logs;
+pub mod metrics;
 pub mod tracing;
 mod resource;
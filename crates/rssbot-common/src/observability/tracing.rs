@@ -1,54 +1,158 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
 use opentelemetry::global;
+use opentelemetry::logs::LoggerProvider as _;
 use opentelemetry_otlp::{
     ExportConfig, HttpExporterBuilder, SpanExporterBuilder, TonicExporterBuilder, WithExportConfig,
 };
+use opentelemetry_sdk::logs::LoggerProvider;
 use opentelemetry_sdk::propagation::TraceContextPropagator;
 use opentelemetry_sdk::runtime::Tokio;
 use opentelemetry_sdk::trace::Sampler;
-use tracing_subscriber::{EnvFilter, Registry};
+use opentelemetry_sdk::Resource;
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::fmt::format::{format, JsonFields};
 use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
 
-use crate::config::{Config, OtelExporter};
+use crate::config::{Config, LogFormat, OtelExporter};
+use crate::observability::logs::{JsonWithTraceIds, OtelLogLayer};
 use crate::observability::resource::init_resource;
 
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// Kept to flush pending log records on shutdown.
+static LOGGER_PROVIDER: OnceLock<LoggerProvider> = OnceLock::new();
+
+/// Targets whose events are not exported over OTLP, as exporting them would emit more of them.
+const EXPORTER_TARGETS: [&str; 5] = ["opentelemetry", "hyper", "h2", "tonic", "reqwest"];
+
+/// Install the global `tracing` subscriber.
+///
+/// Events are written to stdout in the configured format and, when an OTLP exporter is
+/// configured, exported along with spans. Records of the `log` crate go through the same
+/// pipeline.
 pub fn init_tracer(service_name: String, service_version: String, config: &Config) {
-    if let OtelExporter::Unknown = config.otel_exporter {
-        tracing_subscriber::fmt::init();
-        return;
+    let mut layers: Vec<BoxedLayer> = vec![fmt_layer(config.log_format)];
+
+    if !matches!(config.otel_exporter, OtelExporter::Unknown) {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+
+        let resource = init_resource(service_name, service_version);
+
+        let tracer = opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(span_exporter(config))
+            .with_trace_config(
+                opentelemetry_sdk::trace::config()
+                    .with_sampler(Sampler::TraceIdRatioBased(config.otel_sample_rate))
+                    .with_resource(resource.clone()),
+            )
+            .install_batch(Tokio)
+            .expect("Failed to install `opentelemetry` tracer.");
+        layers.push(tracing_opentelemetry::layer().with_tracer(tracer).boxed());
+
+        if config.otel_export_logs {
+            layers.push(otel_log_layer(config, resource));
+        }
     }
 
-    let export_config = ExportConfig {
+    let env_filter = EnvFilter::try_from_default_env().unwrap_or(EnvFilter::new("INFO"));
+    Registry::default()
+        .with(layers)
+        .with(env_filter)
+        .try_init()
+        .expect("Failed to install `tracing` subscriber.");
+}
+
+fn fmt_layer<S>(log_format: LogFormat) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let layer = tracing_subscriber::fmt::layer();
+
+    match log_format {
+        LogFormat::Full => layer.boxed(),
+        LogFormat::Pretty => layer.pretty().boxed(),
+        LogFormat::Compact => layer.compact().boxed(),
+        LogFormat::Json => layer
+            .fmt_fields(JsonFields::new())
+            .event_format(JsonWithTraceIds::new(format().json().flatten_event(true).with_span_list(false)))
+            .boxed(),
+    }
+}
+
+fn export_config(config: &Config) -> ExportConfig {
+    ExportConfig {
         endpoint: config.otel_exporter_endpoint.to_string(),
         ..Default::default()
-    };
+    }
+}
 
-    let exporter = match config.otel_exporter
-    {
+fn span_exporter(config: &Config) -> SpanExporterBuilder {
+    match config.otel_exporter {
         OtelExporter::OtlpHttp => SpanExporterBuilder::Http(
-            HttpExporterBuilder::default().with_export_config(export_config),
+            HttpExporterBuilder::default().with_export_config(export_config(config)),
         ),
         OtelExporter::OtlpGrpc => SpanExporterBuilder::Tonic(
-            TonicExporterBuilder::default().with_export_config(export_config),
+            TonicExporterBuilder::default().with_export_config(export_config(config)),
         ),
-        _ => unreachable!()
-    };
+        OtelExporter::Unknown => unreachable!(),
+    }
+}
 
-    global::set_text_map_propagator(TraceContextPropagator::new());
+fn otel_log_layer(config: &Config, resource: Resource) -> BoxedLayer {
+    let pipeline = opentelemetry_otlp::new_pipeline()
+        .logging()
+        .with_log_config(opentelemetry_sdk::logs::config().with_resource(resource));
+
+    let pipeline = match config.otel_exporter {
+        OtelExporter::OtlpHttp => pipeline.with_exporter(
+            HttpExporterBuilder::default().with_export_config(export_config(config)),
+        ),
+        OtelExporter::OtlpGrpc => pipeline.with_exporter(
+            TonicExporterBuilder::default().with_export_config(export_config(config)),
+        ),
+        OtelExporter::Unknown => unreachable!(),
+    };
 
-    let tracer = opentelemetry_otlp::new_pipeline()
-        .tracing()
-        .with_exporter(exporter)
-        .with_trace_config(
-            opentelemetry_sdk::trace::config()
-                .with_sampler(Sampler::TraceIdRatioBased(config.otel_sample_rate))
-                .with_resource(init_resource(service_name, service_version)),
-        )
+    let provider = pipeline
         .install_batch(Tokio)
-        .expect("Failed to install `opentelemetry` tracer.");
+        .expect("Failed to install `opentelemetry` logger.");
+    let logger = provider.logger_builder(env!("CARGO_PKG_NAME")).build();
+    LOGGER_PROVIDER.set(provider).ok();
 
-    let telemetry = tracing_opentelemetry::layer().with_tracer(tracer);
-    let env_filter = EnvFilter::try_from_default_env().unwrap_or(EnvFilter::new("INFO"));
-    let subscriber = Registry::default().with(telemetry).with(env_filter);
-    tracing::subscriber::set_global_default(subscriber)
-        .expect("Failed to install `tracing` subscriber.");
+    OtelLogLayer::new(logger)
+        .with_filter(filter_fn(|metadata| {
+            !EXPORTER_TARGETS.iter().any(|target| metadata.target().starts_with(target))
+        }))
+        .boxed()
+}
+
+/// W3C trace context headers of the current span, to be set on outbound requests.
+///
+/// Empty when tracing is disabled.
+pub fn trace_headers() -> HashMap<String, String> {
+    let context = tracing::Span::current().context();
+    let mut headers = HashMap::new();
+    global::get_text_map_propagator(|propagator| propagator.inject_context(&context, &mut headers));
+    headers
+}
+
+/// Flush pending spans and log records and shut the exporters down.
+///
+/// This blocks until the exporters are done, call it from a blocking context.
+pub fn shutdown_tracer() {
+    global::shutdown_tracer_provider();
+
+    if let Some(provider) = LOGGER_PROVIDER.get() {
+        if let Err(err) = provider.shutdown() {
+            eprintln!("Failed to shut down the log exporter: {}", err);
+        }
+    }
 }
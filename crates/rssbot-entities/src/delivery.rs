@@ -0,0 +1,48 @@
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, serde::Serialize, serde::Deserialize)]
+#[sea_orm(table_name = "deliveries")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    #[sea_orm(default_expr = "Expr::current_timestamp()")]
+    pub created_at: chrono::NaiveDateTime,
+
+    pub subscription_id: Option<i32>,
+    #[sea_orm(not_null)]
+    pub target_chat: i64,
+    #[sea_orm(not_null, column_type = "Text")]
+    pub text: String,
+    pub parse_mode: Option<String>,
+    pub button_text: Option<String>,
+    pub button_url: Option<String>,
+
+    #[sea_orm(not_null)]
+    pub status: Status,
+    #[sea_orm(not_null)]
+    pub attempts: i32,
+    #[sea_orm(not_null)]
+    pub next_attempt_at: chrono::NaiveDateTime,
+    pub sent_at: Option<chrono::NaiveDateTime>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, serde::Serialize, serde::Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    #[sea_orm(string_value = "pending")]
+    Pending,
+    /// Claimed by a dispatcher, which records how the attempt went.
+    #[sea_orm(string_value = "sending")]
+    Sending,
+    #[sea_orm(string_value = "sent")]
+    Sent,
+    #[sea_orm(string_value = "failed")]
+    Failed,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,19 @@
+use sea_orm::entity::prelude::*;
+
+/// Dialogue state of a chat, used when Redis is not available.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, serde::Serialize, serde::Deserialize)]
+#[sea_orm(table_name = "dialogues")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub chat_id: i64,
+    /// State serialized as JSON.
+    #[sea_orm(not_null, column_type = "Text")]
+    pub state: String,
+    #[sea_orm(not_null)]
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
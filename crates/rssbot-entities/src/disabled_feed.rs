@@ -0,0 +1,17 @@
+use sea_orm::entity::prelude::*;
+
+/// A feed disabled by an administrator, for every subscription to it.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, serde::Serialize, serde::Deserialize)]
+#[sea_orm(table_name = "disabled_feeds")]
+pub struct Model {
+    /// URL of the feed, normalised so that variants of it match too.
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub url: String,
+    #[sea_orm(not_null)]
+    pub disabled_at: chrono::NaiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
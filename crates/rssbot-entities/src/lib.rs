@@ -1,2 +1,5 @@
 pub mod user;
 pub mod subscription;
+pub mod delivery;
+pub mod dialogue;
+pub mod disabled_feed;
@@ -17,8 +17,38 @@ pub struct Model {
 
     #[sea_orm(not_null)]
     pub last_updated: chrono::NaiveDateTime,
+    /// Start of the sync run that last fetched the feed, successfully or not.
+    pub last_fetched: Option<chrono::NaiveDateTime>,
     pub last_sent: Option<chrono::NaiveDateTime>,
     pub last_error: Option<String>,
+
+    pub initial_backfill: Option<i32>,
+    #[sea_orm(not_null)]
+    pub full_text: bool,
+    #[sea_orm(not_null)]
+    pub telegraph: bool,
+    /// Headers and basic auth sent with every fetch of the feed, encrypted.
+    pub credentials: Option<String>,
+
+    /// Metadata of the feed, as of the last successful fetch.
+    pub feed_title: Option<String>,
+    pub feed_link: Option<String>,
+    pub feed_description: Option<String>,
+    pub feed_icon: Option<String>,
+    /// Name chosen by the user, shown instead of the feed title.
+    pub display_name: Option<String>,
+    /// Start messages with the name of the subscription.
+    #[sea_orm(not_null)]
+    pub name_header: bool,
+}
+
+impl Model {
+    /// The display name, the feed title or the URL, whichever is set first.
+    pub fn name(&self) -> &str {
+        self.display_name.as_deref()
+            .or(self.feed_title.as_deref())
+            .unwrap_or(&self.url)
+    }
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
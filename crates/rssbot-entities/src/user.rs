@@ -6,6 +6,13 @@ pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
     pub telegram_user_id: i64,
     pub username: String,
+
+    /// Limits of this user instead of the configured ones.
+    pub max_subscriptions: Option<i32>,
+    pub min_poll_interval_secs: Option<i32>,
+    /// Banned by an administrator, updates of the user are ignored and their feeds not synced.
+    #[sea_orm(not_null)]
+    pub banned: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
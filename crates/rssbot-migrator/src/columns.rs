@@ -0,0 +1,24 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{EntityName, IdenStatic};
+
+/// Add a column to the table of `entity` unless the table has it already.
+///
+/// The first migration creates tables from the entities of the binary running it, so on SQLite a
+/// table may be newer than the migrations adding its columns, and SQLite has neither
+/// `ADD COLUMN IF NOT EXISTS` nor several changes in one `ALTER TABLE`.
+pub async fn add<E: EntityName>(manager: &SchemaManager<'_>, entity: E, column: &mut ColumnDef) -> Result<(), DbErr> {
+    if manager.has_column(entity.table_name(), column.get_column_name()).await? {
+        return Ok(());
+    }
+
+    manager.alter_table(Table::alter().table(entity).add_column(column).to_owned()).await
+}
+
+/// Drop a column of the table of `entity` if the table has it.
+pub async fn drop<E: EntityName, C: IdenStatic>(manager: &SchemaManager<'_>, entity: E, column: C) -> Result<(), DbErr> {
+    if !manager.has_column(entity.table_name(), column.as_str()).await? {
+        return Ok(());
+    }
+
+    manager.alter_table(Table::alter().table(entity).drop_column(column).to_owned()).await
+}
@@ -1,6 +1,20 @@
 pub use sea_orm_migration::prelude::*;
 
+pub mod schema;
+pub mod seed;
+
+mod columns;
+
 mod m20240617_112207_create_table;
+mod m20261018_090000_create_delivery_table;
+mod m20261018_100000_add_subscription_initial_backfill;
+mod m20261018_110000_add_subscription_full_text;
+mod m20261018_120000_add_subscription_telegraph;
+mod m20261018_130000_create_dialogue_table;
+mod m20261019_090000_add_subscription_credentials;
+mod m20261019_100000_add_subscription_feed_metadata;
+mod m20261019_110000_add_limits;
+mod m20261019_120000_add_moderation;
 
 pub struct Migrator;
 
@@ -8,7 +22,16 @@ pub struct Migrator;
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
         vec![
-            Box::new(m20240617_112207_create_table::Migration)
+            Box::new(m20240617_112207_create_table::Migration),
+            Box::new(m20261018_090000_create_delivery_table::Migration),
+            Box::new(m20261018_100000_add_subscription_initial_backfill::Migration),
+            Box::new(m20261018_110000_add_subscription_full_text::Migration),
+            Box::new(m20261018_120000_add_subscription_telegraph::Migration),
+            Box::new(m20261018_130000_create_dialogue_table::Migration),
+            Box::new(m20261019_090000_add_subscription_credentials::Migration),
+            Box::new(m20261019_100000_add_subscription_feed_metadata::Migration),
+            Box::new(m20261019_110000_add_limits::Migration),
+            Box::new(m20261019_120000_add_moderation::Migration),
         ]
     }
 }
@@ -0,0 +1,31 @@
+use sea_orm_migration::prelude::*;
+
+use crate::sea_orm::Schema;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let schema = Schema::new(manager.get_database_backend());
+
+        manager.create_table(schema.create_table_from_entity(rssbot_entities::delivery::Entity)).await?;
+        manager.create_index(
+            Index::create()
+                .name("idx_deliveries_status_next_attempt_at")
+                .table(rssbot_entities::delivery::Entity)
+                .col(rssbot_entities::delivery::Column::Status)
+                .col(rssbot_entities::delivery::Column::NextAttemptAt)
+                .to_owned()
+        ).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_table(Table::drop().table(rssbot_entities::delivery::Entity).if_exists().to_owned()).await?;
+
+        Ok(())
+    }
+}
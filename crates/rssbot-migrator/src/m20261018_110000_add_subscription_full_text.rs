@@ -0,0 +1,19 @@
+use sea_orm_migration::prelude::*;
+
+use rssbot_entities::subscription::{Column, Entity};
+
+use crate::columns;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        columns::add(manager, Entity, ColumnDef::new(Column::FullText).boolean().not_null().default(false)).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        columns::drop(manager, Entity, Column::FullText).await
+    }
+}
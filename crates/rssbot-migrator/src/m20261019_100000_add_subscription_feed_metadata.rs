@@ -0,0 +1,30 @@
+use sea_orm_migration::prelude::*;
+
+use rssbot_entities::subscription::{Column, Entity};
+
+use crate::columns;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+const TEXT_COLUMNS: [Column; 5] = [Column::FeedTitle, Column::FeedLink, Column::FeedDescription, Column::FeedIcon, Column::DisplayName];
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in TEXT_COLUMNS {
+            columns::add(manager, Entity, ColumnDef::new(column).text().null()).await?;
+        }
+        columns::add(manager, Entity, ColumnDef::new(Column::NameHeader).boolean().not_null().default(false)).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in TEXT_COLUMNS.into_iter().chain([Column::NameHeader]) {
+            columns::drop(manager, Entity, column).await?;
+        }
+
+        Ok(())
+    }
+}
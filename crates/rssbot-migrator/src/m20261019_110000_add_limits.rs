@@ -0,0 +1,27 @@
+use sea_orm_migration::prelude::*;
+
+use rssbot_entities::{subscription, user};
+
+use crate::columns;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        columns::add(manager, user::Entity, ColumnDef::new(user::Column::MaxSubscriptions).integer().null()).await?;
+        columns::add(manager, user::Entity, ColumnDef::new(user::Column::MinPollIntervalSecs).integer().null()).await?;
+        columns::add(manager, subscription::Entity, ColumnDef::new(subscription::Column::LastFetched).date_time().null()).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        columns::drop(manager, subscription::Entity, subscription::Column::LastFetched).await?;
+        columns::drop(manager, user::Entity, user::Column::MaxSubscriptions).await?;
+        columns::drop(manager, user::Entity, user::Column::MinPollIntervalSecs).await?;
+
+        Ok(())
+    }
+}
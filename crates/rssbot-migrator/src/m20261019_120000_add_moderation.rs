@@ -0,0 +1,28 @@
+use sea_orm_migration::prelude::*;
+
+use rssbot_entities::{disabled_feed, user};
+
+use crate::columns;
+use crate::sea_orm::Schema;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let schema = Schema::new(manager.get_database_backend());
+
+        columns::add(manager, user::Entity, ColumnDef::new(user::Column::Banned).boolean().not_null().default(false)).await?;
+        manager.create_table(schema.create_table_from_entity(disabled_feed::Entity)).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_table(Table::drop().table(disabled_feed::Entity).if_exists().to_owned()).await?;
+        columns::drop(manager, user::Entity, user::Column::Banned).await?;
+
+        Ok(())
+    }
+}
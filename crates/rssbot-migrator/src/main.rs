@@ -1,6 +1,148 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
 use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{Database, DatabaseConnection};
+
+use rssbot_migrator::schema::{self, Direction, Status};
+use rssbot_migrator::{seed, Migrator};
+
+/// Directory of this crate, whose `src` new migrations are written to.
+const MIGRATION_DIR: &str = "./";
+
+/// Manage the database schema of rssbot.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    /// Database connection URL.
+    #[arg(short = 'u', long, env = "DATABASE_URL", global = true, hide_env_values = true)]
+    database_url: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Show applied and pending migrations.
+    Status,
+    /// Apply pending migrations.
+    Up {
+        /// Number of migrations to apply, all by default.
+        #[arg(short = 'n', long)]
+        num: Option<u32>,
+        /// Print the SQL instead of executing it.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Roll back applied migrations.
+    Down {
+        /// Number of migrations to roll back.
+        #[arg(short = 'n', long, default_value_t = 1)]
+        num: u32,
+        /// Print the SQL instead of executing it.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Drop all tables and apply all migrations.
+    Fresh,
+    /// Roll back all migrations and apply them again.
+    Refresh,
+    /// Roll back all migrations.
+    Reset,
+    /// Insert development data from a TOML file.
+    Seed {
+        file: PathBuf,
+    },
+    /// Create a migration file and add it to the migrator, from the directory of this crate.
+    Generate {
+        /// Name of the migration, e.g. `add_subscription_tags`.
+        name: String,
+        /// Timestamp the migration in local time instead of UTC.
+        #[arg(long)]
+        local_time: bool,
+    },
+}
 
 #[async_std::main]
-async fn main() {
-    cli::run_cli(rssbot_migrator::Migrator).await;
+async fn main() -> ExitCode {
+    dotenv::dotenv().ok();
+    let cli = Cli::parse();
+    let command = cli.command.unwrap_or(Command::Up { num: None, dry_run: false });
+
+    let result = match command {
+        // doesn't need a database
+        Command::Generate { name, local_time } => sea_orm_cli::run_migrate_generate(MIGRATION_DIR, &name, !local_time)
+            .map_err(|err| err.to_string()),
+        command => {
+            let Some(database_url) = cli.database_url else {
+                eprintln!("No database URL, set `DATABASE_URL` or pass `--database-url`.");
+                return ExitCode::from(2);
+            };
+
+            match Database::connect(database_url).await {
+                Ok(db) => run(&db, command).await,
+                Err(err) => Err(err.to_string()),
+            }
+        }
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(db: &DatabaseConnection, command: Command) -> Result<(), String> {
+    match command {
+        Command::Status => {
+            for status in schema::status(db).await.map_err(|err| err.to_string())? {
+                match status {
+                    Status::Applied(name) => println!("applied  {}", name),
+                    Status::Pending(name) => println!("pending  {}", name),
+                    Status::Unknown(name) => println!("unknown  {} (not part of this binary)", name),
+                }
+            }
+        }
+        Command::Up { num, dry_run: true } => print_plan(db, Direction::Up, num).await?,
+        Command::Up { num, dry_run: false } => Migrator::up(db, num).await.map_err(|err| err.to_string())?,
+        Command::Down { num, dry_run: true } => print_plan(db, Direction::Down, Some(num)).await?,
+        Command::Down { num, dry_run: false } => Migrator::down(db, Some(num)).await.map_err(|err| err.to_string())?,
+        Command::Fresh => Migrator::fresh(db).await.map_err(|err| err.to_string())?,
+        Command::Refresh => Migrator::refresh(db).await.map_err(|err| err.to_string())?,
+        Command::Reset => Migrator::reset(db).await.map_err(|err| err.to_string())?,
+        Command::Seed { file } => {
+            let content = std::fs::read_to_string(&file)
+                .map_err(|err| format!("Failed to read {}: {}", file.display(), err))?;
+            let data = toml::from_str::<seed::Seed>(&content)
+                .map_err(|err| format!("Invalid seed file {}: {}", file.display(), err))?;
+
+            let summary = seed::apply(db, &data).await.map_err(|err| err.to_string())?;
+            println!("Inserted {} users and {} subscriptions.", summary.users, summary.subscriptions);
+        }
+        Command::Generate { .. } => unreachable!("migrations are generated without a database"),
+    }
+
+    Ok(())
+}
+
+async fn print_plan(db: &DatabaseConnection, direction: Direction, steps: Option<u32>) -> Result<(), String> {
+    let plan = schema::dry_run(db, direction, steps).await.map_err(|err| err.to_string())?;
+    if plan.is_empty() {
+        println!("-- nothing to do");
+    }
+
+    for (name, statements) in plan {
+        println!("-- {}", name);
+        for statement in statements {
+            println!("{};", statement);
+        }
+        println!();
+    }
+
+    Ok(())
 }
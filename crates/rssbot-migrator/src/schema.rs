@@ -0,0 +1,223 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{
+    ConnectionTrait, Database, DatabaseConnection, DbBackend, EntityTrait, ProxyDatabaseTrait,
+    ProxyExecResult, ProxyRow, QueryResult, Statement, TransactionTrait,
+};
+use sea_orm_migration::seaql_migrations;
+
+use crate::Migrator;
+
+/// Key of the Postgres advisory lock held while migrating, so that replicas starting at the
+/// same time don't run migrations concurrently.
+const MIGRATION_LOCK_KEY: i64 = 0x7273_7362_6f74;
+
+/// Columns of the schema checks of [`SchemaManager`], the only queries migrations run.
+const SCHEMA_CHECKS: [&str; 3] = ["has_table", "has_column", "has_index"];
+
+/// State of a migration, compared between this binary and the database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    Applied(String),
+    Pending(String),
+    /// Applied to the database but unknown to this binary, the schema is newer.
+    Unknown(String),
+}
+
+/// Versions recorded in the migrations table, without creating it when it doesn't exist.
+pub async fn applied_versions(db: &DatabaseConnection) -> Result<HashSet<String>, DbErr> {
+    let manager = SchemaManager::new(db);
+    if !manager.has_table(Migrator::migration_table_name().to_string()).await? {
+        return Ok(HashSet::new());
+    }
+
+    let applied = seaql_migrations::Entity::find().all(db).await?;
+    Ok(applied.into_iter().map(|model| model.version).collect())
+}
+
+/// Status of every migration known to this binary or applied to the database.
+pub async fn status(db: &DatabaseConnection) -> Result<Vec<Status>, DbErr> {
+    let mut applied = applied_versions(db).await?;
+
+    let mut statuses = Migrator::migrations()
+        .iter()
+        .map(|migration| {
+            let name = migration.name().to_string();
+            if applied.remove(&name) { Status::Applied(name) } else { Status::Pending(name) }
+        })
+        .collect::<Vec<_>>();
+
+    let mut unknown = applied.into_iter().collect::<Vec<_>>();
+    unknown.sort();
+    statuses.extend(unknown.into_iter().map(Status::Unknown));
+
+    Ok(statuses)
+}
+
+/// Fail unless the database schema matches the migrations of this binary.
+pub async fn check(db: &DatabaseConnection) -> Result<(), DbErr> {
+    let statuses = status(db).await?;
+
+    let pending = statuses.iter()
+        .filter_map(|status| match status {
+            Status::Pending(name) => Some(name.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if !pending.is_empty() {
+        return Err(DbErr::Custom(format!(
+            "Database schema is out of date, pending migrations: {}. Run the migrator or enable `migrate_on_startup`.",
+            pending.join(", "),
+        )));
+    }
+
+    let unknown = statuses.iter()
+        .filter_map(|status| match status {
+            Status::Unknown(name) => Some(name.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if !unknown.is_empty() {
+        return Err(DbErr::Custom(format!(
+            "Database schema is newer than this binary, unknown migrations: {}.",
+            unknown.join(", "),
+        )));
+    }
+
+    Ok(())
+}
+
+/// Apply pending migrations, holding an advisory lock on Postgres.
+pub async fn migrate(db: &DatabaseConnection) -> Result<(), DbErr> {
+    if db.get_database_backend() != DbBackend::Postgres {
+        return Migrator::up(db, None).await;
+    }
+
+    // the lock is released when the transaction ends
+    let txn = db.begin().await?;
+    txn.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        "SELECT pg_advisory_xact_lock($1)",
+        [MIGRATION_LOCK_KEY.into()],
+    )).await?;
+    Migrator::up(&txn, None).await?;
+    txn.commit().await
+}
+
+/// Direction of a dry run.
+#[derive(Debug, Clone, Copy)]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+/// SQL that applying or rolling back `steps` migrations would run, grouped by migration.
+///
+/// Migrations run against a connection recording statements instead of executing them. The
+/// database is only read, to find out which migrations are applied and to answer the schema
+/// checks of migrations, e.g. whether a column exists. Those see the schema before the run, not
+/// the changes of earlier migrations in the same plan.
+pub async fn dry_run(db: &DatabaseConnection, direction: Direction, steps: Option<u32>) -> Result<Vec<(String, Vec<String>)>, DbErr> {
+    let applied = applied_versions(db).await?;
+    let migrations = Migrator::migrations();
+
+    let mut selected = match direction {
+        Direction::Up => migrations.into_iter()
+            .filter(|migration| !applied.contains(migration.name()))
+            .collect::<Vec<_>>(),
+        Direction::Down => migrations.into_iter()
+            .rev()
+            .filter(|migration| applied.contains(migration.name()))
+            .collect::<Vec<_>>(),
+    };
+    // like `Migrator::down`, roll back a single migration unless told otherwise
+    let steps = match (direction, steps) {
+        (_, Some(steps)) => steps as usize,
+        (Direction::Up, None) => selected.len(),
+        (Direction::Down, None) => 1,
+    };
+    selected.truncate(steps);
+
+    let recorder = StatementRecorder::default();
+    let proxy = Database::connect_proxy(db.get_database_backend(), Arc::new(Mutex::new(Box::new(recorder.clone())))).await?;
+    let manager = SchemaManager::new(&proxy);
+
+    let mut plan = Vec::new();
+    for migration in selected {
+        loop {
+            let result = match direction {
+                Direction::Up => migration.up(&manager).await,
+                Direction::Down => migration.down(&manager).await,
+            };
+            let statements = recorder.take();
+
+            // the proxy can't wait for the database, so run the query here and the migration again
+            if let (Err(_), Some(query)) = (&result, recorder.take_unanswered()) {
+                let rows = db.query_all(query.clone()).await?;
+                let rows = rows.iter().map(schema_check_row).collect::<Result<_, _>>()?;
+                recorder.answer(&query, rows);
+                continue;
+            }
+
+            result?;
+            plan.push((migration.name().to_string(), statements));
+            break;
+        }
+    }
+
+    Ok(plan)
+}
+
+/// Row of a schema check, for the proxy to answer the same check with.
+fn schema_check_row(row: &QueryResult) -> Result<ProxyRow, DbErr> {
+    SCHEMA_CHECKS.iter()
+        .find_map(|column| {
+            let value = row.try_get::<bool>("", column).ok()?;
+            Some(ProxyRow::new(BTreeMap::from([(column.to_string(), value.into())])))
+        })
+        .ok_or_else(|| DbErr::Custom("A dry run can only answer schema checks from the database.".to_string()))
+}
+
+/// Proxy database recording statements instead of executing them.
+///
+/// Queries are answered with the rows given by [`StatementRecorder::answer`], a query without an
+/// answer fails and is kept for [`StatementRecorder::take_unanswered`].
+#[derive(Debug, Clone, Default)]
+struct StatementRecorder {
+    statements: Arc<Mutex<Vec<String>>>,
+    answers: Arc<Mutex<HashMap<String, Vec<ProxyRow>>>>,
+    unanswered: Arc<Mutex<Option<Statement>>>,
+}
+
+impl StatementRecorder {
+    fn take(&self) -> Vec<String> {
+        std::mem::take(&mut *self.statements.lock().unwrap())
+    }
+
+    fn take_unanswered(&self) -> Option<Statement> {
+        self.unanswered.lock().unwrap().take()
+    }
+
+    fn answer(&self, query: &Statement, rows: Vec<ProxyRow>) {
+        self.answers.lock().unwrap().insert(query.to_string(), rows);
+    }
+}
+
+impl ProxyDatabaseTrait for StatementRecorder {
+    fn query(&self, statement: Statement) -> Result<Vec<ProxyRow>, DbErr> {
+        if let Some(rows) = self.answers.lock().unwrap().get(&statement.to_string()) {
+            return Ok(rows.clone());
+        }
+
+        let err = DbErr::Custom(format!("Query not answered by the database yet: {}", statement));
+        *self.unanswered.lock().unwrap() = Some(statement);
+        Err(err)
+    }
+
+    fn execute(&self, statement: Statement) -> Result<ProxyExecResult, DbErr> {
+        self.statements.lock().unwrap().push(statement.to_string());
+        Ok(ProxyExecResult::default())
+    }
+}
@@ -0,0 +1,103 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, TransactionTrait};
+use serde::Deserialize;
+
+use rssbot_entities::{subscription, user};
+
+/// Development data, usually loaded from a TOML file.
+#[derive(Debug, Default, Deserialize)]
+pub struct Seed {
+    #[serde(default)]
+    pub users: Vec<SeedUser>,
+    #[serde(default)]
+    pub subscriptions: Vec<SeedSubscription>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SeedUser {
+    pub telegram_user_id: i64,
+    pub username: String,
+    /// Overrides of the configured limits.
+    pub max_subscriptions: Option<i32>,
+    pub min_poll_interval_secs: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SeedSubscription {
+    pub user_id: i64,
+    /// Chat receiving the updates, the user's private chat by default.
+    pub target_chat: Option<i64>,
+    pub url: String,
+    #[serde(default)]
+    pub full_text: bool,
+    #[serde(default)]
+    pub telegraph: bool,
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub name_header: bool,
+}
+
+/// Number of rows inserted by [`apply`].
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub users: usize,
+    pub subscriptions: usize,
+}
+
+/// Insert the seed data, skipping users and subscriptions that already exist.
+pub async fn apply(db: &DatabaseConnection, seed: &Seed) -> Result<Summary, DbErr> {
+    let txn = db.begin().await?;
+    let mut summary = Summary::default();
+
+    for seed_user in &seed.users {
+        if user::Entity::find_by_id(seed_user.telegram_user_id).one(&txn).await?.is_some() {
+            continue;
+        }
+
+        user::Entity::insert(user::ActiveModel {
+            telegram_user_id: ActiveValue::Set(seed_user.telegram_user_id),
+            username: ActiveValue::Set(seed_user.username.clone()),
+            max_subscriptions: ActiveValue::Set(seed_user.max_subscriptions),
+            min_poll_interval_secs: ActiveValue::Set(seed_user.min_poll_interval_secs),
+            banned: ActiveValue::Set(false),
+        })
+            .exec(&txn)
+            .await?;
+        summary.users += 1;
+    }
+
+    for seed_subscription in &seed.subscriptions {
+        let target_chat = seed_subscription.target_chat.unwrap_or(seed_subscription.user_id);
+        let existing = subscription::Entity::find()
+            .filter(subscription::Column::UserRefer.eq(seed_subscription.user_id))
+            .filter(subscription::Column::TargetChat.eq(target_chat))
+            .filter(subscription::Column::Url.eq(&seed_subscription.url))
+            .one(&txn)
+            .await?;
+        if existing.is_some() {
+            continue;
+        }
+
+        subscription::Entity::insert(subscription::ActiveModel {
+            user_refer: ActiveValue::Set(seed_subscription.user_id),
+            target_chat: ActiveValue::Set(target_chat),
+            url: ActiveValue::Set(seed_subscription.url.clone()),
+            last_updated: ActiveValue::Set(chrono::Utc::now().naive_utc()),
+            last_sent: ActiveValue::Set(None),
+            last_error: ActiveValue::Set(None),
+            initial_backfill: ActiveValue::Set(None),
+            full_text: ActiveValue::Set(seed_subscription.full_text),
+            telegraph: ActiveValue::Set(seed_subscription.telegraph),
+            display_name: ActiveValue::Set(seed_subscription.display_name.clone()),
+            name_header: ActiveValue::Set(seed_subscription.name_header),
+            ..Default::default()
+        })
+            .exec(&txn)
+            .await?;
+        summary.subscriptions += 1;
+    }
+
+    txn.commit().await?;
+
+    Ok(summary)
+}
@@ -0,0 +1,37 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use rssbot_common::config::Sources;
+
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Configuration file, TOML or YAML depending on its extension.
+    #[arg(short, long, env = "CONFIG_FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Override a configuration key, taking precedence over the file and the environment.
+    #[arg(short = 's', long = "set", value_name = "KEY=VALUE", value_parser = parse_override)]
+    pub overrides: Vec<(String, String)>,
+
+    /// Print the effective configuration with secrets redacted, then exit.
+    #[arg(long)]
+    pub print_config: bool,
+}
+
+impl Cli {
+    pub fn sources(&self) -> Sources {
+        Sources {
+            file: self.config.clone(),
+            overrides: self.overrides.clone(),
+        }
+    }
+}
+
+fn parse_override(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => Ok((key.trim().to_lowercase(), value.to_string())),
+        _ => Err(format!("expected `KEY=VALUE`, got {:?}", value)),
+    }
+}
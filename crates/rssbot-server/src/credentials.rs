@@ -0,0 +1,200 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use reqwest::header::{HeaderName, HeaderValue};
+
+use rssbot_common::config::Config;
+
+/// Prefix of sealed credentials, for changing the format later.
+const VERSION: &str = "v1:";
+
+const NONCE_LEN: usize = 12;
+
+/// Headers and basic auth sent with every fetch of a feed, e.g. a private podcast feed.
+#[derive(Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Credentials {
+    /// Header names and values, `Cookie` included.
+    pub headers: Vec<(String, String)>,
+    pub basic_auth: Option<BasicAuth>,
+}
+
+#[derive(Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct BasicAuth {
+    pub username: String,
+    pub password: Option<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Line {0} is neither `Name: value` nor `basic user:password`")]
+    InvalidLine(usize),
+    #[error("Invalid header on line {0}")]
+    InvalidHeader(usize),
+    #[error("No credentials given")]
+    Empty,
+    #[error("No credentials key is configured")]
+    NoKey,
+    #[error("Credentials key must be 32 bytes encoded in base64")]
+    InvalidKey,
+    #[error("Failed to encrypt credentials")]
+    Encrypt,
+    #[error("Failed to decrypt credentials, the credentials key may have changed")]
+    Decrypt,
+    #[error("Failed to encode credentials: {0}")]
+    Encode(#[from] serde_json::Error),
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Credentials({})", self.summary())
+    }
+}
+
+impl Credentials {
+    /// Parse credentials as users send them, one per line: `Name: value` headers, e.g.
+    /// `Cookie: session=abc`, and `basic user:password`.
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut credentials = Self::default();
+
+        for (index, line) in text.lines().enumerate().map(|(index, line)| (index + 1, line.trim())) {
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(user) = line.strip_prefix("basic ").or_else(|| line.strip_prefix("Basic ")) {
+                let (username, password) = match user.trim().split_once(':') {
+                    Some((username, password)) => (username, Some(password.to_string())),
+                    None => (user.trim(), None),
+                };
+                credentials.basic_auth = Some(BasicAuth { username: username.to_string(), password });
+                continue;
+            }
+
+            let (name, value) = line.split_once(':').ok_or(Error::InvalidLine(index))?;
+            let (name, value) = (name.trim(), value.trim());
+            if HeaderName::from_bytes(name.as_bytes()).is_err() || HeaderValue::from_str(value).is_err() {
+                return Err(Error::InvalidHeader(index));
+            }
+            credentials.headers.push((name.to_string(), value.to_string()));
+        }
+
+        if credentials.headers.is_empty() && credentials.basic_auth.is_none() {
+            return Err(Error::Empty);
+        }
+
+        Ok(credentials)
+    }
+
+    /// Add the credentials to `request`, marked sensitive so they are not logged.
+    pub fn apply(&self, mut request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        for (name, value) in &self.headers {
+            let (Ok(name), Ok(mut value)) = (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(value)) else {
+                continue;
+            };
+            value.set_sensitive(true);
+            request = request.header(name, value);
+        }
+
+        match &self.basic_auth {
+            Some(auth) => request.basic_auth(&auth.username, auth.password.as_ref()),
+            None => request,
+        }
+    }
+
+    /// What is sent, without the values.
+    pub fn summary(&self) -> String {
+        self.basic_auth.iter()
+            .map(|auth| format!("basic auth as {}", auth.username))
+            .chain(self.headers.iter().map(|(name, _)| format!("{} header", name)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Encrypts credentials for the database with AES-256-GCM.
+#[derive(Clone)]
+pub struct Cipher(Aes256Gcm);
+
+impl std::fmt::Debug for Cipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Cipher")
+    }
+}
+
+impl Cipher {
+    /// The cipher of `credentials_key`, if one is configured.
+    pub fn from_config(config: &Config) -> Result<Option<Self>, Error> {
+        config.credentials_key.as_deref()
+            .map(|key| BASE64.decode(key.trim()).map_err(|_| Error::InvalidKey).and_then(|key| Self::new(&key)))
+            .transpose()
+    }
+
+    pub fn new(key: &[u8]) -> Result<Self, Error> {
+        Aes256Gcm::new_from_slice(key)
+            .map(Self)
+            .map_err(|_| Error::InvalidKey)
+    }
+
+    /// Encrypt `credentials` into text for the `credentials` column.
+    pub fn seal(&self, credentials: &Credentials) -> Result<String, Error> {
+        let plaintext = serde_json::to_vec(credentials)?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self.0.encrypt(&nonce, plaintext.as_slice()).map_err(|_| Error::Encrypt)?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+
+        Ok(format!("{}{}", VERSION, BASE64.encode(sealed)))
+    }
+
+    /// Decrypt what [`Cipher::seal`] returned.
+    pub fn open(&self, sealed: &str) -> Result<Credentials, Error> {
+        let sealed = sealed.strip_prefix(VERSION)
+            .and_then(|sealed| BASE64.decode(sealed).ok())
+            .filter(|sealed| sealed.len() > NONCE_LEN)
+            .ok_or(Error::Decrypt)?;
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let plaintext = self.0.decrypt(Nonce::from_slice(nonce), ciphertext).map_err(|_| Error::Decrypt)?;
+
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_headers_and_basic_auth() {
+        let credentials = Credentials::parse("basic alice:s3cr:et\n\nCookie: session=abc; theme=dark\nX-Api-Key:  123 ").unwrap();
+
+        assert_eq!(credentials.headers, vec![
+            ("Cookie".to_string(), "session=abc; theme=dark".to_string()),
+            ("X-Api-Key".to_string(), "123".to_string()),
+        ]);
+        assert_eq!(credentials.summary(), "basic auth as alice, Cookie header, X-Api-Key header");
+        let auth = credentials.basic_auth.unwrap();
+        assert_eq!((auth.username.as_str(), auth.password.as_deref()), ("alice", Some("s3cr:et")));
+
+        assert!(matches!(Credentials::parse("just a token"), Err(Error::InvalidLine(1))));
+        assert!(matches!(Credentials::parse("Cookie: a=b\nBad Header: x"), Err(Error::InvalidHeader(2))));
+        assert!(matches!(Credentials::parse(" \n "), Err(Error::Empty)));
+    }
+
+    #[test]
+    fn round_trips_through_the_cipher() {
+        let cipher = Cipher::new(&[7; 32]).unwrap();
+        let credentials = Credentials::parse("Authorization: Bearer token").unwrap();
+
+        let sealed = cipher.seal(&credentials).unwrap();
+        assert!(!sealed.contains("token"));
+        assert_ne!(sealed, cipher.seal(&credentials).unwrap(), "nonces must not repeat");
+        assert_eq!(cipher.open(&sealed).unwrap(), credentials);
+
+        let other = Cipher::new(&[8; 32]).unwrap();
+        assert!(matches!(other.open(&sealed), Err(Error::Decrypt)));
+        assert!(matches!(Cipher::new(&[7; 16]), Err(Error::InvalidKey)));
+    }
+}
@@ -2,4 +2,6 @@
 pub struct SelectChatSessionData {
     pub user_id: i64,
     pub target_url: String,
+    #[serde(default)]
+    pub backfill: i32,
 }
@@ -0,0 +1,121 @@
+use std::convert::Infallible;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{ActiveValue, DatabaseConnection, EntityTrait};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use teloxide::dispatching::dialogue::{serializer, RedisStorage, RedisStorageError, Storage};
+use teloxide::types::ChatId;
+
+use rssbot_entities::dialogue;
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// Dialogue storage in Redis or, for deployments without Redis, in the `dialogues` table.
+///
+/// States are serialized as JSON in both.
+pub struct DialogueStorage<D> {
+    backend: Backend,
+    _dialogue: PhantomData<fn() -> D>,
+}
+
+enum Backend {
+    Redis(Arc<RedisStorage<serializer::Json>>),
+    Database(DatabaseConnection),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Failed to open Redis storage: {0}")]
+    Open(#[from] RedisStorageError<Infallible>),
+    #[error("Redis error: {0}")]
+    Redis(#[from] RedisStorageError<serde_json::Error>),
+    #[error("Database error: {0}")]
+    Database(#[from] sea_orm::DbErr),
+    #[error("Invalid dialogue state: {0}")]
+    Serde(#[from] serde_json::Error),
+    #[error("Dialogue not found")]
+    DialogueNotFound,
+}
+
+impl<D> DialogueStorage<D> {
+    pub async fn redis(url: &str) -> Result<Arc<Self>, Error> {
+        let storage = RedisStorage::open(url, serializer::Json).await?;
+        Ok(Arc::new(Self { backend: Backend::Redis(storage), _dialogue: PhantomData }))
+    }
+
+    pub fn database(db: DatabaseConnection) -> Arc<Self> {
+        Arc::new(Self { backend: Backend::Database(db), _dialogue: PhantomData })
+    }
+}
+
+impl<D> Storage<D> for DialogueStorage<D>
+where
+    D: Serialize + DeserializeOwned + Send + 'static,
+{
+    type Error = Error;
+
+    fn remove_dialogue(self: Arc<Self>, chat_id: ChatId) -> BoxFuture<Result<(), Self::Error>>
+    where
+        D: Send + 'static,
+    {
+        Box::pin(async move {
+            match &self.backend {
+                Backend::Redis(storage) => Ok(Storage::<D>::remove_dialogue(storage.clone(), chat_id).await?),
+                Backend::Database(db) => {
+                    let result = dialogue::Entity::delete_by_id(chat_id.0).exec(db).await?;
+                    if result.rows_affected == 0 {
+                        return Err(Error::DialogueNotFound);
+                    }
+
+                    Ok(())
+                }
+            }
+        })
+    }
+
+    fn update_dialogue(self: Arc<Self>, chat_id: ChatId, state: D) -> BoxFuture<Result<(), Self::Error>>
+    where
+        D: Send + 'static,
+    {
+        Box::pin(async move {
+            match &self.backend {
+                Backend::Redis(storage) => Ok(storage.clone().update_dialogue(chat_id, state).await?),
+                Backend::Database(db) => {
+                    let model = dialogue::ActiveModel {
+                        chat_id: ActiveValue::Set(chat_id.0),
+                        state: ActiveValue::Set(serde_json::to_string(&state)?),
+                        updated_at: ActiveValue::Set(chrono::Utc::now().naive_utc()),
+                    };
+
+                    dialogue::Entity::insert(model)
+                        .on_conflict(
+                            OnConflict::column(dialogue::Column::ChatId)
+                                .update_columns([dialogue::Column::State, dialogue::Column::UpdatedAt])
+                                .to_owned()
+                        )
+                        .exec(db)
+                        .await?;
+
+                    Ok(())
+                }
+            }
+        })
+    }
+
+    fn get_dialogue(self: Arc<Self>, chat_id: ChatId) -> BoxFuture<Result<Option<D>, Self::Error>> {
+        Box::pin(async move {
+            match &self.backend {
+                Backend::Redis(storage) => Ok(storage.clone().get_dialogue(chat_id).await?),
+                Backend::Database(db) => match dialogue::Entity::find_by_id(chat_id.0).one(db).await? {
+                    Some(model) => Ok(Some(serde_json::from_str(&model.state)?)),
+                    None => Ok(None),
+                },
+            }
+        })
+    }
+}
@@ -0,0 +1,360 @@
+use std::str::FromStr;
+
+use chrono::NaiveDateTime;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use quick_xml::events::Event;
+use reqwest::Url;
+
+use rssbot_common::chrono_utils;
+use rssbot_common::config::Config;
+
+/// Query parameters that only serve to track readers.
+const TRACKING_PARAMETERS: [&str; 10] = [
+    "fbclid", "gclid", "dclid", "msclkid", "yclid", "igshid", "mc_cid", "mc_eid", "_hsenc", "_hsmi",
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Remove `utm_*` and other tracking parameters from item URLs.
+    pub strip_tracking_parameters: bool,
+}
+
+impl Options {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            strip_tracking_parameters: config.strip_tracking_parameters,
+        }
+    }
+}
+
+/// A parsed feed, with the base URLs its relative links resolve against.
+#[derive(Debug, Clone)]
+pub struct Feed {
+    pub channel: rss::Channel,
+    /// The feed URL, or the `xml:base` of `rss` or `channel`.
+    base: Url,
+    /// Base URL of each item, empty if they could not be told apart.
+    item_bases: Vec<Url>,
+}
+
+/// What a feed says about itself.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    pub title: Option<String>,
+    /// The site of the feed.
+    pub link: Option<Url>,
+    pub description: Option<String>,
+    /// The channel image, or the iTunes artwork of podcasts.
+    pub icon: Option<Url>,
+}
+
+/// A feed item in the shape the bot delivers it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    pub published: NaiveDateTime,
+    /// Where `published` comes from.
+    pub dated_by: DateSource,
+    pub title: String,
+    pub description: String,
+    /// Full content, e.g. `content:encoded`, when the feed has it.
+    pub content: Option<String>,
+    pub link: Url,
+    pub image: Option<Url>,
+    pub enclosure: Option<Url>,
+}
+
+/// Where the date of an item comes from, from most to least reliable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateSource {
+    /// `pubDate` or `dc:date` of the item.
+    Item,
+    /// The publication or build date of the feed, for items without a usable date.
+    Feed,
+    /// The time the feed was fetched, for feeds without any usable date.
+    Fetch,
+}
+
+/// Why an item is not delivered.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum Skipped {
+    #[error("Item is missing title, description, or link")]
+    MissingFields,
+    #[error("Failed to parse link {0:?}: {1}")]
+    Link(String, String),
+    #[error("Link scheme is not allowed: {0}")]
+    Scheme(Url),
+}
+
+/// Parse the RSS document fetched from `url`, served with `content_type`.
+pub fn parse(body: &[u8], content_type: Option<&str>, url: &Url) -> Result<Feed, rss::Error> {
+    let body = decode(body, content_type);
+    let channel = rss::Channel::from_str(&body)?;
+
+    // `rss` drops `xml:base`, so read it separately; relative links resolve against the feed
+    // URL if that fails
+    let (base, mut item_bases) = xml_bases(&body, url).unwrap_or_else(|| (url.clone(), Vec::new()));
+    if item_bases.len() != channel.items().len() {
+        item_bases.clear();
+    }
+
+    Ok(Feed { channel, base, item_bases })
+}
+
+/// Transcode a feed to UTF-8.
+///
+/// The encoding comes from the byte order mark, then the `Content-Type` charset, then the XML
+/// declaration, as RFC 7303 orders them, and is UTF-8 otherwise. The declaration is rewritten
+/// to UTF-8 so that the parser doesn't decode the text again.
+pub fn decode(body: &[u8], content_type: Option<&str>) -> String {
+    let encoding = Encoding::for_bom(body)
+        .map(|(encoding, _)| encoding)
+        .or_else(|| content_type.and_then(charset))
+        .or_else(|| declared_encoding(body))
+        .unwrap_or(UTF_8);
+
+    let (text, had_errors) = encoding.decode_with_bom_removal(body);
+    if had_errors {
+        tracing::debug!("Feed is not valid {}, replaced invalid sequences", encoding.name());
+    }
+
+    match text.strip_prefix("<?xml").and_then(|rest| rest.split_once("?>")) {
+        Some((declaration, rest)) if declaration.contains("encoding") => format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>{}", rest),
+        _ => text.into_owned(),
+    }
+}
+
+/// The `charset` parameter of a `Content-Type`.
+fn charset(content_type: &str) -> Option<&'static Encoding> {
+    content_type.split(';')
+        .skip(1)
+        .filter_map(|param| param.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+        .and_then(|(_, value)| Encoding::for_label(value.trim().trim_matches('"').as_bytes()))
+}
+
+/// The `encoding` of an XML declaration, which is ASCII in every encoding it can name.
+fn declared_encoding(body: &[u8]) -> Option<&'static Encoding> {
+    let declaration = body.strip_prefix(b"<?xml")?;
+    let end = declaration.iter().take(1024).position(|&b| b == b'>')?;
+    let declaration = std::str::from_utf8(&declaration[..end]).ok()?;
+
+    let value = declaration.split_once("encoding")?.1.trim_start().strip_prefix('=')?.trim_start();
+    let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let label = value[1..].split(quote).next()?;
+
+    // the body is ASCII-compatible since the declaration was read as ASCII, so UTF-16 can't be right
+    Encoding::for_label(label.as_bytes())
+        .map(|encoding| if encoding == UTF_16LE || encoding == UTF_16BE { UTF_8 } else { encoding })
+}
+
+/// Base URLs of the channel and of each item, following nested `xml:base` attributes.
+fn xml_bases(body: &str, url: &Url) -> Option<(Url, Vec<Url>)> {
+    let mut reader = quick_xml::Reader::from_str(body);
+    let mut stack = vec![url.clone()];
+    let mut channel = None;
+    let mut items = Vec::new();
+
+    loop {
+        let (element, empty) = match reader.read_event().ok()? {
+            Event::Start(element) => (element, false),
+            Event::Empty(element) => (element, true),
+            Event::End(_) => {
+                stack.pop();
+                continue;
+            }
+            Event::Eof => break,
+            _ => continue,
+        };
+
+        let parent = stack.last()?.clone();
+        let base = element.attributes()
+            .flatten()
+            .find(|attribute| attribute.key.as_ref() == b"xml:base")
+            .and_then(|attribute| parent.join(attribute.decode_and_unescape_value(&reader).ok()?.trim()).ok())
+            .unwrap_or(parent);
+
+        match element.name().as_ref() {
+            b"channel" if channel.is_none() => channel = Some(base.clone()),
+            b"item" => items.push(base.clone()),
+            _ => {}
+        }
+
+        if !empty {
+            stack.push(base);
+        }
+    }
+
+    Some((channel.unwrap_or_else(|| url.clone()), items))
+}
+
+/// The metadata of a feed, with blank values left out.
+pub fn metadata(feed: &Feed) -> Metadata {
+    let channel = &feed.channel;
+    let text = |value: &str| Some(value.trim()).filter(|value| !value.is_empty()).map(str::to_string);
+    let url = |value: &str| text(value).and_then(|value| feed.base.join(&value).ok()).filter(is_web);
+
+    Metadata {
+        title: text(channel.title()),
+        link: url(channel.link()),
+        description: text(channel.description()),
+        icon: channel.image().map(|image| image.url())
+            .or_else(|| channel.itunes_ext()?.image())
+            .and_then(url),
+    }
+}
+
+/// Normalise the items of a feed fetched at `fetched_at`, in document order.
+pub fn items(feed: &Feed, fetched_at: NaiveDateTime, options: &Options) -> Vec<Result<Item, Skipped>> {
+    let channel = &feed.channel;
+    let feed_date = first_date(
+        channel.pub_date().into_iter()
+            .chain(channel.last_build_date())
+            .chain(channel.dublin_core_ext().into_iter().flat_map(|dc| dc.dates()).map(String::as_str))
+    );
+
+    let fallback = match feed_date {
+        Some(date) => (date, DateSource::Feed),
+        None => (fetched_at, DateSource::Fetch),
+    };
+
+    channel.items()
+        .iter()
+        .enumerate()
+        .map(|(index, item)| {
+            let base = feed.item_bases.get(index).unwrap_or(&feed.base);
+            normalise(item, base, fallback, options)
+        })
+        .collect()
+}
+
+fn normalise(item: &rss::Item, base: &Url, fallback: (NaiveDateTime, DateSource), options: &Options) -> Result<Item, Skipped> {
+    let item_date = first_date(
+        item.pub_date().into_iter()
+            .chain(item.dublin_core_ext().into_iter().flat_map(|dc| dc.dates()).map(String::as_str))
+    );
+
+    let (published, dated_by) = match item_date {
+        Some(date) => (date, DateSource::Item),
+        None => {
+            tracing::debug!("No usable date in item, dating it by {:?}: {:?}", fallback.1, item.pub_date());
+            fallback
+        }
+    };
+
+    let (title, description, link) = match (item.title(), item.description(), item.link()) {
+        (Some(title), Some(description), Some(link)) => (title, description, link),
+        _ => return Err(Skipped::MissingFields),
+    };
+
+    let link = match resolve(base, link, options) {
+        Ok(link) if is_web(&link) => link,
+        Ok(link) => return Err(Skipped::Scheme(link)),
+        Err(err) => return Err(Skipped::Link(link.to_string(), err.to_string())),
+    };
+
+    let enclosure = item.enclosure()
+        .and_then(|enclosure| resolve(base, enclosure.url(), options).ok())
+        .filter(is_web);
+
+    Ok(Item {
+        published,
+        dated_by,
+        title: title.to_string(),
+        description: description.to_string(),
+        content: item.content().map(str::to_string),
+        link,
+        image: image(item).and_then(|url| resolve(base, url, options).ok()).filter(is_web),
+        enclosure,
+    })
+}
+
+/// The item's `media:thumbnail`, image `media:content` or image enclosure.
+fn image(item: &rss::Item) -> Option<&str> {
+    let media = item.extensions().get("media");
+    let media_url = |name: &str| {
+        media?.get(name)?
+            .iter()
+            .find(|extension| name == "thumbnail" || extension.attrs().get("medium").map(String::as_str) == Some("image"))?
+            .attrs()
+            .get("url")
+            .map(String::as_str)
+    };
+
+    media_url("thumbnail")
+        .or_else(|| media_url("content"))
+        .or_else(|| {
+            item.enclosure()
+                .filter(|enclosure| enclosure.mime_type().starts_with("image/"))
+                .map(|enclosure| enclosure.url())
+        })
+}
+
+/// Whether the query parameter `name` only serves to track readers.
+pub fn is_tracking_parameter(name: &str) -> bool {
+    name.starts_with("utm_") || TRACKING_PARAMETERS.contains(&name)
+}
+
+/// Resolve `url` against `base` and clean it up as configured.
+fn resolve(base: &Url, url: &str, options: &Options) -> Result<Url, url::ParseError> {
+    let mut url = base.join(url.trim())?;
+
+    if options.strip_tracking_parameters && url.query().is_some() {
+        let kept = url.query_pairs()
+            .filter(|(name, _)| !is_tracking_parameter(name))
+            .map(|(name, value)| (name.into_owned(), value.into_owned()))
+            .collect::<Vec<_>>();
+
+        if kept.is_empty() {
+            url.set_query(None);
+        } else {
+            url.query_pairs_mut().clear().extend_pairs(kept);
+        }
+    }
+
+    Ok(url)
+}
+
+/// Whether Telegram and the extractor can open `url`.
+fn is_web(url: &Url) -> bool {
+    matches!(url.scheme(), "http" | "https")
+}
+
+/// The first of `dates` that parses.
+fn first_date<'a>(dates: impl IntoIterator<Item = &'a str>) -> Option<NaiveDateTime> {
+    dates.into_iter().find_map(chrono_utils::parse_datetime)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_the_encoding() {
+        let (gbk, _, _) = encoding_rs::GBK.encode("<?xml version=\"1.0\" encoding=\"GBK\"?><title>新闻</title>");
+        assert_eq!(decode(&gbk, None), "<?xml version=\"1.0\" encoding=\"UTF-8\"?><title>新闻</title>");
+        assert_eq!(decode(&gbk, Some("application/rss+xml; charset=gbk")), decode(&gbk, None));
+
+        // the charset of the response wins over the declaration, the byte order mark over both
+        let (latin1, _, _) = encoding_rs::WINDOWS_1252.encode("<?xml version='1.0' encoding='utf-8' standalone='yes'?><title>Café</title>");
+        assert_eq!(decode(&latin1, Some("text/xml; Charset=\"ISO-8859-1\"")), "<?xml version=\"1.0\" encoding=\"UTF-8\"?><title>Café</title>");
+        assert_eq!(decode(b"\xEF\xBB\xBF<title>Caf\xC3\xA9</title>", Some("text/xml; charset=windows-1251")), "<title>Café</title>");
+
+        let utf16 = [&[0xFF, 0xFE][..], &"<title>Café</title>".encode_utf16().flat_map(u16::to_le_bytes).collect::<Vec<_>>()].concat();
+        assert_eq!(decode(&utf16, None), "<title>Café</title>");
+
+        // without any hint, UTF-8, and unknown labels are ignored
+        assert_eq!(decode("<?xml version=\"1.0\"?><title>Café</title>".as_bytes(), Some("text/xml")), "<?xml version=\"1.0\"?><title>Café</title>");
+        assert_eq!(decode("<?xml version=\"1.0\" encoding=\"x-unknown\"?><a>é</a>".as_bytes(), None), "<?xml version=\"1.0\" encoding=\"UTF-8\"?><a>é</a>");
+    }
+
+    #[test]
+    fn strips_tracking_parameters() {
+        let base = Url::parse("https://example.com/blog/").unwrap();
+        let strip = Options { strip_tracking_parameters: true };
+
+        let url = "post?utm_source=rss&id=7&fbclid=abc&utm_medium=feed";
+        assert_eq!(resolve(&base, url, &strip).unwrap().as_str(), "https://example.com/blog/post?id=7");
+        assert_eq!(resolve(&base, url, &Options::default()).unwrap().as_str(), format!("https://example.com/blog/{}", url));
+        assert_eq!(resolve(&base, "/post?utm_campaign=x#top", &strip).unwrap().as_str(), "https://example.com/post#top");
+    }
+}
@@ -0,0 +1,378 @@
+use std::error::Error as StdError;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use ipnet::IpNet;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{Response, Url};
+
+use rssbot_common::config::Config;
+
+use crate::http::TracePropagation;
+use crate::webhook::parse_ip_range;
+
+/// Rules for fetching URLs that users control, i.e. feeds and the pages they link to.
+///
+/// Hosts must resolve to public addresses, unless an address is explicitly allowed, and
+/// every redirect is checked again. The resolver only hands allowed addresses to the
+/// connector, so a host can't resolve to a public address for the check and a private
+/// one for the connection.
+///
+/// Through a proxy, hosts are resolved by the proxy instead and only IP literals are checked.
+#[derive(Clone)]
+pub struct Policy {
+    /// Ranges that may be fetched even though they are private, e.g. an internal feed host.
+    pub allowed: Arc<[IpNet]>,
+    pub max_redirects: usize,
+    /// Maximum size of a response body.
+    pub max_bytes: usize,
+    /// Timeout of a whole request, including the body.
+    pub timeout: Duration,
+    pub connect_timeout: Duration,
+    pub user_agent: String,
+    /// HTTP or SOCKS proxy URL, proxy environment variables are ignored.
+    pub proxy: Option<String>,
+    pub trace_propagation: TracePropagation,
+}
+
+/// Why a URL may not be fetched.
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum Denied {
+    #[error("URL scheme is not allowed: {0}")]
+    Scheme(String),
+    #[error("Address is not allowed: {0}")]
+    Address(IpAddr),
+    #[error("Host only resolves to addresses that are not allowed: {0}")]
+    Host(String),
+    #[error("More than {0} redirects")]
+    Redirects(usize),
+    #[error("Redirect to another origin is not allowed with credentials: {0}")]
+    CrossOrigin(String),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{0}")]
+    Denied(#[from] Denied),
+    #[error("Request timed out")]
+    Timeout,
+    #[error("Response exceeds {0} bytes")]
+    TooLarge(usize),
+    #[error("Request failed: {0}")]
+    Request(reqwest::Error),
+    #[error("Failed to resolve {0}: {1}")]
+    Resolve(String, std::io::Error),
+    #[error("Invalid IP range: {0}")]
+    InvalidIpRange(String),
+    #[error("Invalid proxy: {0}")]
+    InvalidProxy(reqwest::Error),
+}
+
+impl From<reqwest::Error> for Error {
+    /// Surface a denial from the resolver or the redirect policy instead of the generic
+    /// connection or redirect error wrapping it.
+    fn from(err: reqwest::Error) -> Self {
+        let mut source = err.source();
+        while let Some(inner) = source {
+            if let Some(denied) = inner.downcast_ref::<Denied>() {
+                return Error::Denied(denied.clone());
+            }
+            source = inner.source();
+        }
+
+        if err.is_timeout() {
+            return Error::Timeout;
+        }
+
+        Error::Request(err)
+    }
+}
+
+impl std::fmt::Debug for Policy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // the proxy URL may carry a password
+        f.debug_struct("Policy")
+            .field("allowed", &self.allowed)
+            .field("max_redirects", &self.max_redirects)
+            .field("max_bytes", &self.max_bytes)
+            .field("timeout", &self.timeout)
+            .field("connect_timeout", &self.connect_timeout)
+            .field("user_agent", &self.user_agent)
+            .field("proxy", &self.proxy.as_ref().map(|_| "<redacted>"))
+            .field("trace_propagation", &self.trace_propagation)
+            .finish()
+    }
+}
+
+impl Policy {
+    pub fn from_config(config: &Config) -> Result<Self, Error> {
+        let allowed = config.fetch_allowed_ips.iter()
+            .map(|range| parse_ip_range(range).ok_or_else(|| Error::InvalidIpRange(range.clone())))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            allowed: allowed.into(),
+            max_redirects: config.fetch_max_redirects,
+            max_bytes: config.fetch_max_bytes,
+            timeout: Duration::from_millis(config.fetch_timeout_ms),
+            connect_timeout: Duration::from_millis(config.fetch_connect_timeout_ms),
+            user_agent: config.fetch_user_agent.clone().unwrap_or_else(default_user_agent),
+            proxy: config.fetch_proxy.clone(),
+            trace_propagation: TracePropagation::from_config(config),
+        })
+    }
+
+    /// Finish clients from `builder` enforcing this policy on every connection and redirect.
+    pub fn build(self, builder: impl Fn() -> reqwest::ClientBuilder) -> Result<Client, Error> {
+        Ok(Client {
+            inner: self.client(builder(), false)?,
+            same_origin: self.client(builder(), true)?,
+            policy: self,
+        })
+    }
+
+    fn client(&self, builder: reqwest::ClientBuilder, same_origin: bool) -> Result<reqwest::Client, Error> {
+        let allowed = self.allowed.clone();
+        let max_redirects = self.max_redirects;
+        let redirect = reqwest::redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() > max_redirects {
+                return attempt.error(Denied::Redirects(max_redirects));
+            }
+            // the first URL is the one requested
+            let origin = attempt.url().origin();
+            if same_origin && attempt.previous().first().is_some_and(|first| first.origin() != origin) {
+                return attempt.error(Denied::CrossOrigin(origin.ascii_serialization()));
+            }
+
+            match check_url(&allowed, attempt.url()) {
+                Ok(()) => attempt.follow(),
+                Err(denied) => attempt.error(denied),
+            }
+        });
+
+        let builder = match &self.proxy {
+            Some(proxy) => builder.proxy(reqwest::Proxy::all(proxy).map_err(Error::InvalidProxy)?),
+            None => builder
+                .no_proxy()
+                .dns_resolver(Arc::new(Resolver { allowed: self.allowed.clone() })),
+        };
+
+        Ok(builder
+            .redirect(redirect)
+            .user_agent(&self.user_agent)
+            .timeout(self.timeout)
+            .connect_timeout(self.connect_timeout)
+            .build()?)
+    }
+}
+
+/// An HTTP client for user-supplied URLs, see [`Policy`].
+#[derive(Debug, Clone)]
+pub struct Client {
+    inner: reqwest::Client,
+    /// Refuses redirects to other origins, for requests carrying credentials.
+    same_origin: reqwest::Client,
+    policy: Policy,
+}
+
+impl Client {
+    pub fn get(&self, url: Url) -> reqwest::RequestBuilder {
+        self.inner.get(url)
+    }
+
+    /// Like [`Client::get`], but redirects may not leave the origin of `url`, so credentials
+    /// added to the request are not sent anywhere else.
+    pub fn get_same_origin(&self, url: Url) -> reqwest::RequestBuilder {
+        self.same_origin.get(url)
+    }
+
+    /// Send a request built with this client.
+    pub async fn send(&self, request: reqwest::RequestBuilder) -> Result<Response, Error> {
+        let (client, request) = request.build_split();
+        let mut request = request?;
+        // requests to IP addresses don't go through the resolver
+        check_url(&self.policy.allowed, request.url())?;
+        self.policy.trace_propagation.apply(&mut request);
+
+        Ok(client.execute(request).await?)
+    }
+
+    /// Check `url` up front, resolving its host, to reject it before it's stored.
+    pub async fn check(&self, url: &Url) -> Result<(), Error> {
+        check_url(&self.policy.allowed, url)?;
+        // the proxy resolves hosts
+        if self.policy.proxy.is_some() {
+            return Ok(());
+        }
+
+        if let Some(url::Host::Domain(host)) = url.host() {
+            let port = url.port_or_known_default().unwrap_or_default();
+            let addrs = tokio::net::lookup_host((host, port)).await
+                .map_err(|err| Error::Resolve(host.to_string(), err))?;
+            allowed_addrs(&self.policy.allowed, host, addrs)?;
+        }
+
+        Ok(())
+    }
+
+    /// Read the body, failing once it exceeds the size limit.
+    pub async fn read_body(&self, mut response: Response) -> Result<Vec<u8>, Error> {
+        let max_bytes = self.policy.max_bytes;
+        if response.content_length().is_some_and(|len| len > max_bytes as u64) {
+            return Err(Error::TooLarge(max_bytes));
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if body.len() + chunk.len() > max_bytes {
+                return Err(Error::TooLarge(max_bytes));
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        Ok(body)
+    }
+}
+
+fn default_user_agent() -> String {
+    format!("rssbot/{} (+https://github.com/AH-dark/rssbot)", env!("CARGO_PKG_VERSION"))
+}
+
+/// Resolves hosts to their allowed addresses only.
+struct Resolver {
+    allowed: Arc<[IpNet]>,
+}
+
+impl Resolve for Resolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let allowed = self.allowed.clone();
+        Box::pin(async move {
+            let host = name.as_str();
+            let addrs = tokio::net::lookup_host((host, 0)).await?;
+            let addrs: Addrs = Box::new(allowed_addrs(&allowed, host, addrs)?.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+fn allowed_addrs(allowed: &[IpNet], host: &str, addrs: impl Iterator<Item = SocketAddr>) -> Result<Vec<SocketAddr>, Denied> {
+    let addrs = addrs.filter(|addr| is_allowed(allowed, addr.ip())).collect::<Vec<_>>();
+    if addrs.is_empty() {
+        tracing::warn!("Refusing to fetch from {}, it only resolves to addresses that are not allowed", host);
+        return Err(Denied::Host(host.to_string()));
+    }
+
+    Ok(addrs)
+}
+
+/// Check the scheme and, for IP literals, the address; hosts are checked when resolved.
+fn check_url(allowed: &[IpNet], url: &Url) -> Result<(), Denied> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(Denied::Scheme(url.scheme().to_string()));
+    }
+
+    let ip = match url.host() {
+        Some(url::Host::Ipv4(ip)) => IpAddr::V4(ip),
+        Some(url::Host::Ipv6(ip)) => IpAddr::V6(ip),
+        _ => return Ok(()),
+    };
+
+    if !is_allowed(allowed, ip) {
+        return Err(Denied::Address(ip));
+    }
+
+    Ok(())
+}
+
+fn is_allowed(allowed: &[IpNet], ip: IpAddr) -> bool {
+    allowed.iter().any(|range| range.contains(&ip)) || is_public(ip)
+}
+
+/// Whether `ip` is globally routable, i.e. not loopback, private, link-local or otherwise
+/// special purpose.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(ip),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        // "this network", shared address space (CGNAT), IETF protocol assignments,
+        // benchmarking and reserved
+        || a == 0
+        || (a == 100 && (64..128).contains(&b))
+        || (a == 192 && b == 0 && c == 0)
+        || (a == 198 && (18..20).contains(&b))
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let segments = ip.segments();
+
+    // NAT64 and 6to4 addresses embed an IPv4 address
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        let [a, b] = segments[6].to_be_bytes();
+        let [c, d] = segments[7].to_be_bytes();
+        return is_public_v4(Ipv4Addr::new(a, b, c, d));
+    }
+    if segments[0] == 0x2002 {
+        let [a, b] = segments[1].to_be_bytes();
+        let [c, d] = segments[2].to_be_bytes();
+        return is_public_v4(Ipv4Addr::new(a, b, c, d));
+    }
+
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // unique local, link-local and deprecated site-local
+        || (segments[0] & 0xfe00) == 0xfc00
+        || (segments[0] & 0xffc0) == 0xfe80
+        || (segments[0] & 0xffc0) == 0xfec0
+        // documentation
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_internal_addresses() {
+        for ip in [
+            "127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "0.0.0.0", "100.64.0.1",
+            "255.255.255.255", "::1", "::", "fd00::1", "fe80::1", "::ffff:127.0.0.1", "64:ff9b::a9fe:a9fe", "2002:7f00:1::",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+
+        for ip in ["1.1.1.1", "93.184.216.34", "2606:4700:4700::1111", "::ffff:1.1.1.1"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn checks_literal_addresses_and_schemes() {
+        let allowed = [parse_ip_range("10.0.0.0/8").unwrap()];
+        let check = |url: &str| check_url(&allowed, &url.parse().unwrap());
+
+        assert!(check("https://example.com/feed").is_ok());
+        assert!(check("http://10.1.2.3/feed").is_ok());
+        assert!(matches!(check("http://169.254.169.254/latest/meta-data"), Err(Denied::Address(_))));
+        assert!(matches!(check("http://[::1]:8080/"), Err(Denied::Address(_))));
+        assert!(matches!(check("file:///etc/passwd"), Err(Denied::Scheme(_))));
+    }
+}
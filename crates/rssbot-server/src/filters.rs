@@ -1,11 +1,34 @@
+use std::sync::Arc;
+
 use teloxide::prelude::*;
 
+use crate::services::{admin, user};
+
 #[tracing::instrument]
 pub fn private_message_only(update: Update) -> bool {
-    update.chat().map_or(true, |chat| chat.is_private())
+    update.chat().is_none_or(|chat| chat.is_private())
 }
 
 #[tracing::instrument]
 pub fn channel_or_group(update: Update) -> bool {
-    update.chat().map_or(true, |chat| chat.is_channel() || chat.is_group() || chat.is_supergroup())
+    update.chat().is_none_or(|chat| chat.is_channel() || chat.is_group() || chat.is_supergroup())
+}
+
+#[tracing::instrument(skip_all, fields(update_id = update.id, user_id = update.user().map(|user| user.id.0)))]
+pub fn admin_only(update: Update, admin: Arc<admin::Service>) -> bool {
+    update.user().is_some_and(|user| admin.is_admin(user.id.0 as i64))
+}
+
+/// Updates of banned users, which are ignored.
+#[tracing::instrument(skip_all, fields(update_id = update.id, user_id = update.user().map(|user| user.id.0)))]
+pub async fn banned_user(update: Update, user_service: Arc<user::Service>) -> bool {
+    let Some(user) = update.user() else { return false };
+
+    match user_service.is_banned(user.id.0 as i64).await {
+        Ok(banned) => banned,
+        Err(err) => {
+            tracing::warn!("Failed to check whether user {} is banned: {}", user.id, err);
+            false
+        }
+    }
 }
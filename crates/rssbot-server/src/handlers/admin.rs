@@ -0,0 +1,240 @@
+use std::sync::Arc;
+
+use reqwest::Url;
+use teloxide::Bot;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+use teloxide::utils::command::BotCommands;
+use teloxide::utils::html;
+
+use crate::handlers::subscription_link;
+use crate::services::{admin, subscription, user};
+
+/// Most subscriptions listed by `/subscriptions`, to stay within the length of a message.
+const LIST_LIMIT: u64 = 20;
+
+#[derive(Debug, Clone, BotCommands)]
+#[command(rename_rule = "lowercase", description = "Administrator commands:")]
+/// Commands of the configured administrators, in private chats
+pub enum Command {
+    #[command(description = "Display administrator commands")]
+    Admin,
+    #[command(description = "Show statistics of all users")]
+    Stats,
+    #[command(description = "List subscriptions of all users, optionally matching a URL, name, user or chat")]
+    Subscriptions(String),
+    #[command(description = "Sync a subscription now: <subscription ID>")]
+    ForceSync(String),
+    #[command(description = "Stop syncing a feed for everyone: <URL or subscription ID>")]
+    Disable(String),
+    #[command(description = "Resume syncing a feed: <URL or subscription ID>")]
+    Enable(String),
+    #[command(description = "Ignore a user and stop syncing their feeds: <user ID>")]
+    Ban(String),
+    #[command(description = "Lift the ban of a user: <user ID>")]
+    Unban(String),
+    #[command(description = "Set the limits of a user: <user ID> <subscriptions|default> <poll interval in seconds|default>")]
+    Limits(String),
+    #[command(description = "Send an announcement to every user with a subscription: <text>")]
+    Broadcast(String),
+}
+
+#[tracing::instrument]
+pub async fn handle_admin(message: Message, bot: Bot) -> anyhow::Result<()> {
+    bot.send_message(message.chat.id, Command::descriptions().to_string()).await?;
+    Ok(())
+}
+
+#[tracing::instrument]
+pub async fn handle_stats(message: Message, bot: Bot, admin_service: Arc<admin::Service>) -> anyhow::Result<()> {
+    let stats = admin_service.stats().await?;
+
+    bot.send_message(message.chat.id, format!(
+        "Users: {} ({} banned)\nSubscriptions: {} to {} feeds\nDisabled feeds: {}\nFailing: {}\nDeliveries: {} pending, {} failed",
+        stats.users,
+        stats.banned_users,
+        stats.subscriptions,
+        stats.feeds,
+        stats.disabled_feeds,
+        stats.failing_subscriptions,
+        stats.pending_deliveries,
+        stats.failed_deliveries,
+    )).await?;
+
+    Ok(())
+}
+
+#[tracing::instrument]
+pub async fn handle_subscriptions(message: Message, bot: Bot, query: String, service: Arc<subscription::Service>) -> anyhow::Result<()> {
+    let subscriptions = service.search_subscriptions(query.trim(), LIST_LIMIT).await?;
+    if subscriptions.is_empty() {
+        bot.send_message(message.chat.id, "No subscriptions found").await?;
+        return Ok(());
+    }
+    let disabled = service.disabled_feeds().await?;
+
+    let content = subscriptions.iter()
+        .fold(format!("<b>Latest {} subscriptions:</b>\n", subscriptions.len()), |acc, sub| {
+            format!(
+                "{}\nID {}: {}{}\nUser <code>{}</code> -> Chat <code>{}</code>\nLast Error: {}\n",
+                acc,
+                sub.id,
+                subscription_link(sub),
+                if disabled.contains(&subscription::feed_key(&sub.url)) { " (disabled)" } else { "" },
+                sub.user_refer,
+                sub.target_chat,
+                sub.last_error.as_deref().map(html::escape).unwrap_or("N/A".to_string()),
+            )
+        });
+
+    bot.send_message(message.chat.id, content).parse_mode(ParseMode::Html).await?;
+
+    Ok(())
+}
+
+#[tracing::instrument]
+pub async fn handle_force_sync(message: Message, bot: Bot, id: String, service: Arc<subscription::Service>) -> anyhow::Result<()> {
+    let Ok(id) = id.trim().parse::<i32>() else {
+        bot.send_message(message.chat.id, "Usage: /forcesync <subscription ID>").await?;
+        return Ok(());
+    };
+
+    let text = match service.force_sync(id).await {
+        Ok(subscription) => match &subscription.last_error {
+            None => format!("Synced {}", subscription_link(&subscription)),
+            Some(err) => format!("Failed to sync {}: {}", subscription_link(&subscription), html::escape(err)),
+        },
+        Err(subscription::Error::SubscriptionNotFound) => "Subscription not found".to_string(),
+        Err(err) => return Err(err.into()),
+    };
+
+    bot.send_message(message.chat.id, text).parse_mode(ParseMode::Html).await?;
+
+    Ok(())
+}
+
+#[tracing::instrument]
+pub async fn handle_disable(message: Message, bot: Bot, target: String, service: Arc<subscription::Service>) -> anyhow::Result<()> {
+    set_feed_disabled(message, bot, target, service, true).await
+}
+
+#[tracing::instrument]
+pub async fn handle_enable(message: Message, bot: Bot, target: String, service: Arc<subscription::Service>) -> anyhow::Result<()> {
+    set_feed_disabled(message, bot, target, service, false).await
+}
+
+async fn set_feed_disabled(message: Message, bot: Bot, target: String, service: Arc<subscription::Service>, disabled: bool) -> anyhow::Result<()> {
+    let target = target.trim();
+    let url = match target.parse::<i32>() {
+        Ok(id) => match service.get_subscription(id).await {
+            Ok(subscription) => Some(subscription.url),
+            Err(subscription::Error::SubscriptionNotFound) => None,
+            Err(err) => return Err(err.into()),
+        },
+        Err(_) => Url::parse(target).is_ok().then(|| target.to_string()),
+    };
+    let Some(url) = url else {
+        let command = if disabled { "disable" } else { "enable" };
+        bot.send_message(message.chat.id, format!("Usage: /{} <URL or subscription ID>", command)).await?;
+        return Ok(());
+    };
+
+    let count = service.set_feed_disabled(&url, disabled).await?;
+    let text = match disabled {
+        true => format!("Disabled {} for {} subscriptions", url, count),
+        false => format!("Enabled {} for {} subscriptions", url, count),
+    };
+    bot.send_message(message.chat.id, text).await?;
+
+    Ok(())
+}
+
+#[tracing::instrument]
+pub async fn handle_ban(message: Message, bot: Bot, user_id: String, admin_service: Arc<admin::Service>, user_service: Arc<user::Service>) -> anyhow::Result<()> {
+    let Ok(user_id) = user_id.trim().parse::<i64>() else {
+        bot.send_message(message.chat.id, "Usage: /ban <user ID>").await?;
+        return Ok(());
+    };
+    if admin_service.is_admin(user_id) {
+        bot.send_message(message.chat.id, "Administrators can't be banned").await?;
+        return Ok(());
+    }
+
+    set_banned(message, bot, user_id, user_service, true).await
+}
+
+#[tracing::instrument]
+pub async fn handle_unban(message: Message, bot: Bot, user_id: String, user_service: Arc<user::Service>) -> anyhow::Result<()> {
+    let Ok(user_id) = user_id.trim().parse::<i64>() else {
+        bot.send_message(message.chat.id, "Usage: /unban <user ID>").await?;
+        return Ok(());
+    };
+
+    set_banned(message, bot, user_id, user_service, false).await
+}
+
+async fn set_banned(message: Message, bot: Bot, user_id: i64, user_service: Arc<user::Service>, banned: bool) -> anyhow::Result<()> {
+    let text = match user_service.set_banned(user_id, banned).await {
+        Ok(user) if banned => format!("Banned {} ({})", user.username, user.telegram_user_id),
+        Ok(user) => format!("Unbanned {} ({})", user.username, user.telegram_user_id),
+        Err(user::Error::UserNotFound) => "User not found".to_string(),
+        Err(err) => return Err(err.into()),
+    };
+    bot.send_message(message.chat.id, text).await?;
+
+    Ok(())
+}
+
+#[tracing::instrument]
+pub async fn handle_limits(message: Message, bot: Bot, args: String, user_service: Arc<user::Service>) -> anyhow::Result<()> {
+    // `default` goes back to the configured limit
+    fn limit(arg: &str) -> Option<Option<i32>> {
+        match arg {
+            "default" => Some(None),
+            _ => arg.parse::<i32>().ok().filter(|limit| *limit >= 0).map(Some),
+        }
+    }
+
+    let args = args.split_whitespace().collect::<Vec<_>>();
+    let parsed = match args.as_slice() {
+        [user_id, subscriptions, interval] => user_id.parse::<i64>().ok().zip(limit(subscriptions)).zip(limit(interval)),
+        _ => None,
+    };
+    let Some(((user_id, max_subscriptions), min_poll_interval_secs)) = parsed else {
+        bot.send_message(message.chat.id, "Usage: /limits <user ID> <subscriptions|default> <poll interval in seconds|default>").await?;
+        return Ok(());
+    };
+
+    let text = match user_service.set_limits(user_id, max_subscriptions, min_poll_interval_secs).await {
+        Ok(user) => format!(
+            "Limits of {} ({}): {} subscriptions, polled every {}",
+            user.username,
+            user.telegram_user_id,
+            match user.max_subscriptions {
+                None => "default".to_string(),
+                Some(0) => "unlimited".to_string(),
+                Some(limit) => limit.to_string(),
+            },
+            user.min_poll_interval_secs.map_or("default interval".to_string(), |secs| format!("{} seconds", secs)),
+        ),
+        Err(user::Error::UserNotFound) => "User not found".to_string(),
+        Err(err) => return Err(err.into()),
+    };
+    bot.send_message(message.chat.id, text).await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip(text))]
+pub async fn handle_broadcast(message: Message, bot: Bot, text: String, admin_service: Arc<admin::Service>) -> anyhow::Result<()> {
+    let text = text.trim();
+    if text.is_empty() {
+        bot.send_message(message.chat.id, "Usage: /broadcast <text>").await?;
+        return Ok(());
+    }
+
+    let count = admin_service.broadcast(text).await?;
+    bot.send_message(message.chat.id, format!("Announcement queued for {} users", count)).await?;
+
+    Ok(())
+}
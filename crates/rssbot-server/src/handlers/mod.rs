@@ -1,2 +1,117 @@
+use teloxide::dispatching::UpdateHandler;
+use teloxide::prelude::*;
+use teloxide::types::UpdateKind;
+use teloxide::utils::html;
+
+use rssbot_common::observability::metrics::metrics;
+use rssbot_entities::subscription;
+
+use crate::dialogue::DialogueStorage;
+use crate::filters;
+
+pub mod admin;
 pub mod private;
 pub mod public;
+
+/// The update handler tree.
+///
+/// Expects the dialogue storage, the subscription, user, admin and cache services in the dependencies.
+pub fn schema() -> UpdateHandler<anyhow::Error> {
+    let private_message_handlers = dptree::entry()
+        .filter(filters::private_message_only)
+        .branch(
+            Update::filter_message()
+                .filter(filters::admin_only)
+                .filter_command::<admin::Command>()
+                .branch(dptree::case![admin::Command::Admin].endpoint(admin::handle_admin))
+                .branch(dptree::case![admin::Command::Stats].endpoint(admin::handle_stats))
+                .branch(dptree::case![admin::Command::Subscriptions(query)].endpoint(admin::handle_subscriptions))
+                .branch(dptree::case![admin::Command::ForceSync(id)].endpoint(admin::handle_force_sync))
+                .branch(dptree::case![admin::Command::Disable(target)].endpoint(admin::handle_disable))
+                .branch(dptree::case![admin::Command::Enable(target)].endpoint(admin::handle_enable))
+                .branch(dptree::case![admin::Command::Ban(user_id)].endpoint(admin::handle_ban))
+                .branch(dptree::case![admin::Command::Unban(user_id)].endpoint(admin::handle_unban))
+                .branch(dptree::case![admin::Command::Limits(args)].endpoint(admin::handle_limits))
+                .branch(dptree::case![admin::Command::Broadcast(text)].endpoint(admin::handle_broadcast))
+        )
+        .branch(
+            Update::filter_message()
+                .enter_dialogue::<Message, DialogueStorage<private::State>, private::State>()
+                .branch(
+                    dptree::case![private::State::Unstated]
+                        .filter_command::<private::UnstatedCommand>()
+                        .branch(dptree::case![private::UnstatedCommand::Start].endpoint(private::handle_start))
+                        .branch(dptree::case![private::UnstatedCommand::Help].endpoint(private::handle_help))
+                        .branch(dptree::case![private::UnstatedCommand::Subscribe].endpoint(private::handle_subscribe_command))
+                        .branch(dptree::case![private::UnstatedCommand::List].endpoint(private::handle_list_command))
+                        .branch(dptree::case![private::UnstatedCommand::Unsubscribe].endpoint(private::handle_unsubscribe_command))
+                        .branch(dptree::case![private::UnstatedCommand::FullText].endpoint(private::handle_full_text_command))
+                        .branch(dptree::case![private::UnstatedCommand::Telegraph].endpoint(private::handle_telegraph_command))
+                        .branch(dptree::case![private::UnstatedCommand::Credentials].endpoint(private::handle_credentials_command))
+                        .branch(dptree::case![private::UnstatedCommand::Rename].endpoint(private::handle_rename_command))
+                        .branch(dptree::case![private::UnstatedCommand::Header].endpoint(private::handle_header_command))
+                )
+                .branch(dptree::case![private::State::SubscribeWaitingUrl].endpoint(private::handle_subscribe_enter_url))
+                .branch(dptree::case![private::State::CredentialsWaitingInput { id }].endpoint(private::handle_credentials_input))
+                .branch(dptree::case![private::State::RenameWaitingInput { id }].endpoint(private::handle_rename_input))
+        )
+        .branch(
+            Update::filter_callback_query()
+                .enter_dialogue::<CallbackQuery, DialogueStorage<private::State>, private::State>()
+                .branch(dptree::case![private::State::SubscribeWaitingBackfill { url }].endpoint(private::handle_subscribe_backfill_callback))
+                .branch(dptree::case![private::State::UnsubscribeWaitingCallbackQuery].endpoint(private::handle_unsubscribe_callback))
+                .branch(dptree::case![private::State::FullTextWaitingCallbackQuery].endpoint(private::handle_full_text_callback))
+                .branch(dptree::case![private::State::TelegraphWaitingCallbackQuery].endpoint(private::handle_telegraph_callback))
+                .branch(dptree::case![private::State::CredentialsWaitingCallbackQuery].endpoint(private::handle_credentials_callback))
+                .branch(dptree::case![private::State::RenameWaitingCallbackQuery].endpoint(private::handle_rename_callback))
+                .branch(dptree::case![private::State::HeaderWaitingCallbackQuery].endpoint(private::handle_header_callback))
+        );
+
+    let channel_or_group_handlers = dptree::entry()
+        .filter(filters::channel_or_group)
+        .branch(
+            Update::filter_message()
+                .filter_command::<public::Command>()
+                .branch(dptree::case![public::Command::Start { id }].endpoint(public::handle_start))
+                .branch(dptree::case![public::Command::Help].endpoint(public::handle_unstated_help))
+                .branch(dptree::case![public::Command::List].endpoint(public::handle_list))
+        );
+
+    dptree::entry()
+        .inspect(count_update)
+        .branch(dptree::filter_async(filters::banned_user).endpoint(ignore_banned_user))
+        .branch(channel_or_group_handlers)
+        .branch(private_message_handlers)
+}
+
+/// The name of a subscription linking to its feed, for HTML messages.
+pub fn subscription_link(subscription: &subscription::Model) -> String {
+    format!("<a href=\"{}\">{}</a>", html::escape(&subscription.url), html::escape(subscription.name()))
+}
+
+/// Count incoming updates by kind.
+pub fn count_update(update: Update) {
+    let kind = match update.kind {
+        UpdateKind::Message(_) => "message",
+        UpdateKind::EditedMessage(_) => "edited_message",
+        UpdateKind::ChannelPost(_) => "channel_post",
+        UpdateKind::EditedChannelPost(_) => "edited_channel_post",
+        UpdateKind::CallbackQuery(_) => "callback_query",
+        UpdateKind::MyChatMember(_) => "my_chat_member",
+        _ => "other",
+    };
+
+    metrics().updates_received.with_label_values(&[kind]).inc();
+}
+
+/// Drop updates of banned users without answering them.
+pub async fn ignore_banned_user(update: Update) -> anyhow::Result<()> {
+    tracing::debug!("Ignoring update of banned user {:?}", update.user().map(|user| user.id));
+    Ok(())
+}
+
+/// Log errors returned by handlers and count them.
+pub async fn handle_error(err: anyhow::Error) {
+    metrics().handler_errors.inc();
+    tracing::error!("Error in handler: {:?}", err);
+}
@@ -1,17 +1,18 @@
 use std::sync::Arc;
 
-use redis::aio::MultiplexedConnection;
-use redis::AsyncCommands;
 use reqwest::Url;
 use teloxide::Bot;
-use teloxide::dispatching::dialogue::{RedisStorage, serializer};
 use teloxide::prelude::*;
 use teloxide::types::{InlineKeyboardButton, InlineKeyboardButtonKind, InlineKeyboardMarkup, Me, ParseMode};
 use teloxide::types::ReplyMarkup::InlineKeyboard;
 use teloxide::utils::command::BotCommands;
+use teloxide::utils::html;
 
+use crate::credentials::Credentials;
 use crate::data::SelectChatSessionData;
-use crate::services::{subscription, user};
+use crate::dialogue::DialogueStorage;
+use crate::handlers::subscription_link;
+use crate::services::{cache, subscription, user};
 
 #[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
 #[serde(rename_all = "snake_case", tag = "state", content = "data")]
@@ -19,10 +20,24 @@ pub enum State {
     #[default]
     Unstated,
     SubscribeWaitingUrl,
+    SubscribeWaitingBackfill { url: String },
     UnsubscribeWaitingCallbackQuery,
+    FullTextWaitingCallbackQuery,
+    TelegraphWaitingCallbackQuery,
+    CredentialsWaitingCallbackQuery,
+    CredentialsWaitingInput { id: i32 },
+    RenameWaitingCallbackQuery,
+    RenameWaitingInput { id: i32 },
+    HeaderWaitingCallbackQuery,
 }
 
-type BotDialog = Dialogue<State, RedisStorage<serializer::Json>>;
+/// Number of latest items that can be sent right after subscribing, `0` only sends future items.
+const BACKFILL_CHOICES: [i32; 4] = [0, 1, 5, 10];
+
+/// Longest custom name of a subscription, to keep keyboards and listings readable.
+const MAX_NAME_CHARS: usize = 64;
+
+type BotDialog = Dialogue<State, DialogueStorage<State>>;
 
 #[derive(Debug, Clone, BotCommands)]
 #[command(rename_rule = "lowercase", description = "These commands are supported:")]
@@ -38,6 +53,16 @@ pub enum UnstatedCommand {
     Unsubscribe,
     #[command(description = "List all subscriptions")]
     List,
+    #[command(description = "Toggle fetching full articles for a subscription")]
+    FullText,
+    #[command(description = "Toggle publishing items to Telegraph for a subscription")]
+    Telegraph,
+    #[command(description = "Set headers or basic auth for fetching a subscription")]
+    Credentials,
+    #[command(description = "Set a custom name for a subscription")]
+    Rename,
+    #[command(description = "Toggle starting messages with the subscription name")]
+    Header,
 }
 
 #[tracing::instrument]
@@ -106,20 +131,26 @@ pub async fn handle_help(message: Message, bot: Bot, user_service: Arc<user::Ser
 }
 
 #[tracing::instrument(skip(dialog))]
-pub async fn handle_subscribe_command(message: Message, bot: Bot, dialog: BotDialog) -> anyhow::Result<()> {
+pub async fn handle_subscribe_command(message: Message, bot: Bot, dialog: BotDialog, service: Arc<subscription::Service>) -> anyhow::Result<()> {
+    // tell users over their limit before they pick a feed, chats are checked once one is picked
+    if let Some(user) = message.from() {
+        match service.check_quota(user.id.0 as i64, None).await {
+            Ok(()) => {}
+            Err(err @ (subscription::Error::UserQuotaExceeded(_) | subscription::Error::GlobalQuotaExceeded)) => {
+                bot.send_message(message.chat.id, err.to_string()).await?;
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+
     dialog.update(State::SubscribeWaitingUrl).await?;
     bot.send_message(message.chat.id, "Enter the URL of the RSS feed you want to subscribe to.").await?;
     Ok(())
 }
 
 #[tracing::instrument(skip(dialog))]
-pub async fn handle_subscribe_enter_url(message: Message, bot: Bot, dialog: BotDialog, me: Me, mut redis_con: MultiplexedConnection) -> anyhow::Result<()> {
-    let user_id = message.from().map(|user| user.id.0 as i64);
-    if user_id.is_none() {
-        bot.send_message(message.chat.id, "User ID not found").await?;
-        return Ok(());
-    }
-
+pub async fn handle_subscribe_enter_url(message: Message, bot: Bot, dialog: BotDialog, subscription_service: Arc<subscription::Service>) -> anyhow::Result<()> {
     let url = match message.text() {
         Some(url) => match url.parse::<Url>() {
             Ok(url) => url,
@@ -134,83 +165,408 @@ pub async fn handle_subscribe_enter_url(message: Message, bot: Bot, dialog: BotD
         }
     };
 
+    if let Err(err) = subscription_service.check_url(&url).await {
+        bot.send_message(message.chat.id, format!("Can't subscribe to this URL: {}", err)).await?;
+        return Ok(());
+    }
+
+    let buttons = BACKFILL_CHOICES.iter()
+        .map(|&count| {
+            let text = match count {
+                0 => "Only new posts".to_string(),
+                1 => "Latest post".to_string(),
+                n => format!("Latest {} posts", n),
+            };
+            InlineKeyboardButton::new(text, InlineKeyboardButtonKind::CallbackData(count.to_string()))
+        })
+        .collect::<Vec<_>>();
+
+    bot
+        .send_message(message.chat.id, "Should I send some of the latest posts right away, or only new ones?")
+        .reply_markup(InlineKeyboard(InlineKeyboardMarkup::new(
+            buttons.chunks(1)
+                .map(|row| row.to_vec())
+                .collect::<Vec<_>>()
+        )))
+        .await?;
+
+    dialog.update(State::SubscribeWaitingBackfill { url: url.to_string() }).await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip(dialog))]
+pub async fn handle_subscribe_backfill_callback(query: CallbackQuery, bot: Bot, dialog: BotDialog, url: String, me: Me, cache: Arc<cache::Service>) -> anyhow::Result<()> {
+    let backfill = match query.data.as_deref().and_then(|data| data.parse::<i32>().ok()) {
+        Some(count) if BACKFILL_CHOICES.contains(&count) => count,
+        _ => {
+            bot.answer_callback_query(query.id).text("Invalid choice").send().await?;
+            return Ok(());
+        }
+    };
+
+    bot.answer_callback_query(query.id).send().await?;
+
     let chat_selection_id = uuid::Uuid::new_v4().to_string();
     let link = format!("t.me/{}?startgroup={}", me.username.as_ref().unwrap(), chat_selection_id);
 
     let sess_data = SelectChatSessionData {
-        user_id: user_id.unwrap(),
-        target_url: url.to_string(),
+        user_id: query.from.id.0 as i64,
+        target_url: url,
+        backfill,
     };
-    redis_con.set_ex(chat_selection_id, serde_json::to_string(&sess_data)?, 5 * 60).await?;
-    bot.send_message(message.chat.id, format!("Select a chat to receive updates: {}, expires in 5 minutes.", link)).await?;
+    cache.set_ex(&chat_selection_id, &serde_json::to_string(&sess_data)?, 5 * 60).await?;
+    bot.send_message(query.from.id, format!("Select a chat to receive updates: {}, expires in 5 minutes.", link)).await?;
 
     dialog.reset().await?;
 
     Ok(())
 }
 
+/// The keyboards listing a user's subscriptions, each answered by its own callback handler.
+#[derive(Debug, Clone, Copy)]
+enum Picker {
+    Unsubscribe,
+    FullText,
+    Telegraph,
+    Credentials,
+    Rename,
+    Header,
+}
+
+impl Picker {
+    /// Prefix of the callback data, so a stale keyboard can't act on behalf of another one.
+    fn prefix(self) -> &'static str {
+        match self {
+            Picker::Unsubscribe => "unsubscribe",
+            Picker::FullText => "fulltext",
+            Picker::Telegraph => "telegraph",
+            Picker::Credentials => "credentials",
+            Picker::Rename => "rename",
+            Picker::Header => "header",
+        }
+    }
+
+    fn prompt(self) -> &'static str {
+        match self {
+            Picker::Unsubscribe => "Select a subscription to unsubscribe from",
+            Picker::FullText => "Select a subscription to toggle fetching full articles for",
+            Picker::Telegraph => "Select a subscription to toggle publishing items to Telegraph for",
+            Picker::Credentials => "Select a subscription to set credentials for",
+            Picker::Rename => "Select a subscription to rename",
+            Picker::Header => "Select a subscription to toggle starting messages with its name for",
+        }
+    }
+
+    fn state(self) -> State {
+        match self {
+            Picker::Unsubscribe => State::UnsubscribeWaitingCallbackQuery,
+            Picker::FullText => State::FullTextWaitingCallbackQuery,
+            Picker::Telegraph => State::TelegraphWaitingCallbackQuery,
+            Picker::Credentials => State::CredentialsWaitingCallbackQuery,
+            Picker::Rename => State::RenameWaitingCallbackQuery,
+            Picker::Header => State::HeaderWaitingCallbackQuery,
+        }
+    }
+
+    /// The current setting shown in front of each subscription.
+    fn status(self, sub: &rssbot_entities::subscription::Model) -> Option<&'static str> {
+        let on_off = |enabled: bool| if enabled { "on" } else { "off" };
+        match self {
+            Picker::Unsubscribe | Picker::Rename => None,
+            Picker::FullText => Some(on_off(sub.full_text)),
+            Picker::Telegraph => Some(on_off(sub.telegraph)),
+            Picker::Credentials => Some(if sub.credentials.is_some() { "set" } else { "none" }),
+            Picker::Header => Some(on_off(sub.name_header)),
+        }
+    }
+
+    /// Sends the keyboard listing the user's subscriptions and waits for the callback.
+    async fn send(self, message: &Message, bot: &Bot, dialog: &BotDialog, service: &subscription::Service) -> anyhow::Result<()> {
+        let Some(user_id) = message.from().map(|user| user.id.0 as i64) else {
+            bot.send_message(message.chat.id, "User ID not found").await?;
+            return Ok(());
+        };
+
+        let subscriptions = service.list_subscriptions(user_id).await?;
+        if subscriptions.is_empty() {
+            bot.send_message(message.chat.id, "You have no subscriptions").await?;
+            return Ok(());
+        }
+
+        let mut buttons = subscriptions.iter()
+            .map(|sub| {
+                let text = match self.status(sub) {
+                    Some(status) => format!("[{}] {} -> {}", status, sub.name(), sub.target_chat),
+                    None => format!("{} -> {}", sub.name(), sub.target_chat),
+                };
+                InlineKeyboardButton::new(text, InlineKeyboardButtonKind::CallbackData(format!("{}:{}", self.prefix(), sub.id)))
+            })
+            .collect::<Vec<_>>();
+
+        buttons.push(InlineKeyboardButton::new("Cancel", InlineKeyboardButtonKind::CallbackData("cancel".to_string())));
+
+        bot
+            .send_message(message.chat.id, self.prompt())
+            .reply_markup(InlineKeyboard(InlineKeyboardMarkup::new(
+                buttons.chunks(1)
+                    .map(|row| row.to_vec())
+                    .collect::<Vec<_>>()
+            )))
+            .await?;
+
+        dialog.update(self.state()).await?;
+
+        Ok(())
+    }
+
+    /// Resolves the picked subscription among the user's own, `None` once the query has been answered.
+    async fn picked(self, query: &CallbackQuery, bot: &Bot, dialog: &BotDialog, service: &subscription::Service) -> anyhow::Result<Option<rssbot_entities::subscription::Model>> {
+        let data = query.data.as_deref().unwrap_or_default();
+        if data == "cancel" {
+            bot.answer_callback_query(query.id.clone()).text("Cancelled").send().await?;
+            if let Some(msg) = &query.message { bot.delete_message(msg.chat.id, msg.id).send().await.ok(); }
+            dialog.reset().await?;
+            return Ok(None);
+        }
+
+        let id = data.strip_prefix(self.prefix())
+            .and_then(|rest| rest.strip_prefix(':'))
+            .and_then(|id| id.parse::<i32>().ok());
+        let subscription = match id {
+            Some(id) => service.list_subscriptions(query.from.id.0 as i64).await?.into_iter().find(|sub| sub.id == id),
+            None => None,
+        };
+        if subscription.is_none() {
+            bot.answer_callback_query(query.id.clone()).text("Invalid subscription ID").send().await?;
+        }
+
+        Ok(subscription)
+    }
+}
+
 #[tracing::instrument(skip(dialog))]
 pub async fn handle_unsubscribe_command(message: Message, bot: Bot, dialog: BotDialog, service: Arc<subscription::Service>) -> anyhow::Result<()> {
-    let user_id = message.from().map(|user| user.id.0 as i64);
-    if user_id.is_none() {
-        bot.send_message(message.chat.id, "User ID not found").await?;
+    Picker::Unsubscribe.send(&message, &bot, &dialog, &service).await
+}
+
+#[tracing::instrument(skip(dialog))]
+pub async fn handle_unsubscribe_callback(query: CallbackQuery, bot: Bot, service: Arc<subscription::Service>, dialog: BotDialog) -> anyhow::Result<()> {
+    let Some(sub) = Picker::Unsubscribe.picked(&query, &bot, &dialog, &service).await? else {
         return Ok(());
+    };
+
+    match service.remove_subscription(sub.user_refer, sub.id).await {
+        Ok(_) => {
+            bot.answer_callback_query(query.id).text("Subscription removed").send().await?;
+        }
+        Err(e) => {
+            bot.answer_callback_query(query.id).text(e.to_string()).send().await?;
+            return Err(e.into());
+        }
     }
 
-    let subscriptions = service.list_subscriptions(user_id.unwrap()).await?;
-    if subscriptions.is_empty() {
-        bot.send_message(message.chat.id, "You have no subscriptions").await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(dialog))]
+pub async fn handle_full_text_command(message: Message, bot: Bot, dialog: BotDialog, service: Arc<subscription::Service>) -> anyhow::Result<()> {
+    Picker::FullText.send(&message, &bot, &dialog, &service).await
+}
+
+#[tracing::instrument(skip(dialog))]
+pub async fn handle_full_text_callback(query: CallbackQuery, bot: Bot, service: Arc<subscription::Service>, dialog: BotDialog) -> anyhow::Result<()> {
+    let Some(sub) = Picker::FullText.picked(&query, &bot, &dialog, &service).await? else {
         return Ok(());
+    };
+
+    match service.set_full_text(sub.user_refer, sub.id, !sub.full_text).await {
+        Ok(sub) => {
+            let text = if sub.full_text { "Full articles will be fetched" } else { "Full articles will no longer be fetched" };
+            bot.answer_callback_query(query.id).text(text).send().await?;
+        }
+        Err(e) => {
+            bot.answer_callback_query(query.id).text(e.to_string()).send().await?;
+            return Err(e.into());
+        }
     }
 
-    let mut buttons = subscriptions.iter()
-        .map(|sub| {
-            InlineKeyboardButton::new(format!(
-                "{} -> {}",
-                sub.url,
-                sub.target_chat
-            ), InlineKeyboardButtonKind::CallbackData(sub.id.to_string()))
-        })
-        .collect::<Vec<_>>();
+    dialog.reset().await?;
 
-    buttons.push(InlineKeyboardButton::new("Cancel", InlineKeyboardButtonKind::CallbackData("cancel".to_string())));
+    Ok(())
+}
 
-    bot
-        .send_message(message.chat.id, "Select a subscription to unsubscribe from")
-        .reply_markup(InlineKeyboard(InlineKeyboardMarkup::new(
-            buttons.chunks(1)
-                .map(|row| row.to_vec())
-                .collect::<Vec<_>>()
-        )))
-        .await?;
+#[tracing::instrument(skip(dialog))]
+pub async fn handle_telegraph_command(message: Message, bot: Bot, dialog: BotDialog, service: Arc<subscription::Service>) -> anyhow::Result<()> {
+    Picker::Telegraph.send(&message, &bot, &dialog, &service).await
+}
+
+#[tracing::instrument(skip(dialog))]
+pub async fn handle_telegraph_callback(query: CallbackQuery, bot: Bot, service: Arc<subscription::Service>, dialog: BotDialog) -> anyhow::Result<()> {
+    let Some(sub) = Picker::Telegraph.picked(&query, &bot, &dialog, &service).await? else {
+        return Ok(());
+    };
 
-    dialog.update(State::UnsubscribeWaitingCallbackQuery).await?;
+    match service.set_telegraph(sub.user_refer, sub.id, !sub.telegraph).await {
+        Ok(sub) => {
+            let text = if sub.telegraph { "Items will be published to Telegraph" } else { "Items will be sent inline" };
+            bot.answer_callback_query(query.id).text(text).send().await?;
+        }
+        Err(e) => {
+            bot.answer_callback_query(query.id).text(e.to_string()).send().await?;
+            return Err(e.into());
+        }
+    }
+
+    dialog.reset().await?;
 
     Ok(())
 }
 
 #[tracing::instrument(skip(dialog))]
-pub async fn handle_unsubscribe_callback(query: CallbackQuery, bot: Bot, service: Arc<subscription::Service>, dialog: BotDialog) -> anyhow::Result<()> {
-    let data = query.data.unwrap_or_default();
-    if data == "cancel" {
-        bot.answer_callback_query(query.id).text("Cancelled").send().await?;
-        if let Some(msg) = query.message { bot.delete_message(msg.chat.id, msg.id).send().await.ok(); }
-        dialog.reset().await?;
+pub async fn handle_credentials_command(message: Message, bot: Bot, dialog: BotDialog, service: Arc<subscription::Service>) -> anyhow::Result<()> {
+    Picker::Credentials.send(&message, &bot, &dialog, &service).await
+}
+
+#[tracing::instrument(skip(dialog))]
+pub async fn handle_credentials_callback(query: CallbackQuery, bot: Bot, service: Arc<subscription::Service>, dialog: BotDialog) -> anyhow::Result<()> {
+    let Some(subscription) = Picker::Credentials.picked(&query, &bot, &dialog, &service).await? else {
         return Ok(());
+    };
+
+    bot.answer_callback_query(query.id).send().await?;
+    bot.send_message(query.from.id, format!(
+        "Send the credentials for {}, one per line:\n\
+        Header-Name: value, e.g. Cookie: session=abc\n\
+        basic user:password\n\n\
+        Send \"clear\" to remove them, or \"cancel\" to keep them as they are.",
+        subscription.url,
+    )).await?;
+
+    dialog.update(State::CredentialsWaitingInput { id: subscription.id }).await?;
+
+    Ok(())
+}
+
+/// The message holds secrets, so it's neither traced nor kept in the chat.
+#[tracing::instrument(skip(message, dialog))]
+pub async fn handle_credentials_input(message: Message, bot: Bot, dialog: BotDialog, id: i32, service: Arc<subscription::Service>) -> anyhow::Result<()> {
+    let user_id = message.from().map(|user| user.id.0 as i64);
+    let (Some(user_id), Some(text)) = (user_id, message.text()) else {
+        bot.send_message(message.chat.id, "Send the credentials as text").await?;
+        return Ok(());
+    };
+
+    let credentials = match text.trim() {
+        "cancel" => {
+            bot.send_message(message.chat.id, "Cancelled").await?;
+            dialog.reset().await?;
+            return Ok(());
+        }
+        "clear" => None,
+        text => match Credentials::parse(text) {
+            Ok(credentials) => Some(credentials),
+            Err(err) => {
+                bot.delete_message(message.chat.id, message.id).await.ok();
+                bot.send_message(message.chat.id, format!("Invalid credentials: {}", err)).await?;
+                return Ok(());
+            }
+        },
+    };
+
+    bot.delete_message(message.chat.id, message.id).await.ok();
+
+    let summary = credentials.as_ref().map(Credentials::summary);
+    match service.set_credentials(user_id, id, credentials).await {
+        Ok(sub) => {
+            let text = match summary {
+                Some(summary) => format!("Credentials saved for {}: {}", sub.name(), summary),
+                None => format!("Credentials removed for {}", sub.name()),
+            };
+            bot.send_message(message.chat.id, text).await?;
+        }
+        Err(e) => {
+            bot.send_message(message.chat.id, e.to_string()).await?;
+            dialog.reset().await?;
+            return Err(e.into());
+        }
     }
 
-    let user_id = query.from.id.0 as i64;
-    let subscription_id = match data.parse::<i32>() {
-        Ok(id) => id,
-        Err(_) => {
-            bot.answer_callback_query(query.id).text("Invalid subscription ID").send().await?;
+    dialog.reset().await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip(dialog))]
+pub async fn handle_rename_command(message: Message, bot: Bot, dialog: BotDialog, service: Arc<subscription::Service>) -> anyhow::Result<()> {
+    Picker::Rename.send(&message, &bot, &dialog, &service).await
+}
+
+#[tracing::instrument(skip(dialog))]
+pub async fn handle_rename_callback(query: CallbackQuery, bot: Bot, service: Arc<subscription::Service>, dialog: BotDialog) -> anyhow::Result<()> {
+    let Some(subscription) = Picker::Rename.picked(&query, &bot, &dialog, &service).await? else {
+        return Ok(());
+    };
+
+    bot.answer_callback_query(query.id).send().await?;
+    bot.send_message(query.from.id, format!(
+        "Send the new name for {}, or \"clear\" to use the feed title.",
+        subscription.name(),
+    )).await?;
+
+    dialog.update(State::RenameWaitingInput { id: subscription.id }).await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip(dialog))]
+pub async fn handle_rename_input(message: Message, bot: Bot, dialog: BotDialog, id: i32, service: Arc<subscription::Service>) -> anyhow::Result<()> {
+    let user_id = message.from().map(|user| user.id.0 as i64);
+    let (Some(user_id), Some(text)) = (user_id, message.text()) else {
+        bot.send_message(message.chat.id, "Send the name as text").await?;
+        return Ok(());
+    };
+
+    let name = match text.trim() {
+        "clear" => None,
+        name if name.chars().count() > MAX_NAME_CHARS => {
+            bot.send_message(message.chat.id, format!("The name must be at most {} characters", MAX_NAME_CHARS)).await?;
             return Ok(());
         }
+        name => Some(name.to_string()),
     };
 
-    match service.remove_subscription(user_id, subscription_id).await {
-        Ok(_) => {
-            bot.answer_callback_query(query.id).text("Subscription removed").send().await?;
+    match service.set_display_name(user_id, id, name).await {
+        Ok(sub) => {
+            bot.send_message(message.chat.id, format!("Subscription renamed to {}", sub.name())).await?;
+        }
+        Err(e) => {
+            bot.send_message(message.chat.id, e.to_string()).await?;
+            dialog.reset().await?;
+            return Err(e.into());
+        }
+    }
+
+    dialog.reset().await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip(dialog))]
+pub async fn handle_header_command(message: Message, bot: Bot, dialog: BotDialog, service: Arc<subscription::Service>) -> anyhow::Result<()> {
+    Picker::Header.send(&message, &bot, &dialog, &service).await
+}
+
+#[tracing::instrument(skip(dialog))]
+pub async fn handle_header_callback(query: CallbackQuery, bot: Bot, service: Arc<subscription::Service>, dialog: BotDialog) -> anyhow::Result<()> {
+    let Some(sub) = Picker::Header.picked(&query, &bot, &dialog, &service).await? else {
+        return Ok(());
+    };
+
+    match service.set_name_header(sub.user_refer, sub.id, !sub.name_header).await {
+        Ok(sub) => {
+            let text = if sub.name_header { "Messages will start with the subscription name" } else { "Messages will no longer start with the subscription name" };
+            bot.answer_callback_query(query.id).text(text).send().await?;
         }
         Err(e) => {
             bot.answer_callback_query(query.id).text(e.to_string()).send().await?;
@@ -218,6 +574,8 @@ pub async fn handle_unsubscribe_callback(query: CallbackQuery, bot: Bot, service
         }
     }
 
+    dialog.reset().await?;
+
     Ok(())
 }
 
@@ -234,30 +592,37 @@ pub async fn handle_list_command(message: Message, bot: Bot, dialog: BotDialog,
         bot.send_message(message.chat.id, "You have no subscriptions").await?;
         return Ok(());
     }
+    let disabled_feeds = service.disabled_feeds().await?;
 
     let content = subscriptions.iter()
         .fold("<b>Your subscriptions:</b>\n".to_string(), |acc, sub| {
-            let last_updated = sub.last_updated.format("%Y-%m-%d %H:%M:%S").to_string();
-
-            let last_sent = sub.last_sent
+            let last_fetched = sub.last_fetched
                 .map(|date| date.format("%Y-%m-%d %H:%M:%S").to_string())
                 .unwrap_or("N/A".to_string());
 
-            let last_error = sub.last_error.clone()
+            let last_error = sub.last_error.as_deref()
+                .map(html::escape)
                 .unwrap_or("N/A".to_string());
 
+            let site = sub.feed_link.as_deref()
+                .map(html::escape)
+                .unwrap_or("N/A".to_string());
+
+            let disabled = if disabled_feeds.contains(&subscription::feed_key(&sub.url)) { " (disabled)" } else { "" };
+
             format!(r#"{}
 
-            ID {}: {} -> Chat `{}`
-            Last Updated: {}
-            Last Sent: {}
+            ID {}: {}{} -> Chat `{}`
+            Site: {}
+            Last Fetched: {}
             Last Error: {}"#,
                     acc,
                     sub.id,
-                    sub.url,
+                    subscription_link(sub),
+                    disabled,
                     sub.target_chat,
-                    last_updated,
-                    last_sent,
+                    site,
+                    last_fetched,
                     last_error
             )
                 .split('\n')
@@ -2,13 +2,12 @@ use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use anyhow::Context;
-use redis::aio::MultiplexedConnection;
-use redis::AsyncCommands;
 use teloxide::prelude::*;
 use teloxide::types::ParseMode;
 use teloxide::utils::command::BotCommands;
 
 use crate::data::SelectChatSessionData;
+use crate::handlers::subscription_link;
 use crate::services;
 
 #[derive(Debug, Clone, BotCommands)]
@@ -24,7 +23,7 @@ pub enum Command {
 }
 
 #[tracing::instrument]
-pub async fn handle_start(bot: Bot, message: Message, mut redis_con: MultiplexedConnection, service: Arc<services::subscription::Service>, command: Command) -> anyhow::Result<()> {
+pub async fn handle_start(bot: Bot, message: Message, cache: Arc<services::cache::Service>, service: Arc<services::subscription::Service>, command: Command) -> anyhow::Result<()> {
     let id = &(match command {
         Command::Start { id } => id,
         _ => {
@@ -34,11 +33,8 @@ pub async fn handle_start(bot: Bot, message: Message, mut redis_con: Multiplexed
     });
 
     let sess_data: SelectChatSessionData = {
-        let result = redis_con.get(id).await;
-        // delete redis key immediately
-        redis_con.del(id).await?;
-
-        let record: Option<String> = match result {
+        // the session is deleted as it is read
+        let record = match cache.take(id).await {
             Ok(record) => record,
             Err(err) => {
                 bot.send_message(message.chat.id, "Server internal error").await?;
@@ -61,7 +57,7 @@ pub async fn handle_start(bot: Bot, message: Message, mut redis_con: Multiplexed
         }
     };
 
-    match service.add_subscription(sess_data.user_id, message.chat.id.0, sess_data.target_url).await {
+    match service.add_subscription(sess_data.user_id, message.chat.id.0, sess_data.target_url, sess_data.backfill).await {
         Ok(subscription) => {
             // notify chat
             if message.chat.is_group() || message.chat.is_supergroup() {
@@ -74,11 +70,11 @@ pub async fn handle_start(bot: Bot, message: Message, mut redis_con: Multiplexed
             // notify user
             bot.send_message(UserId(sess_data.user_id as u64), format!("Subscription has been added to chat {}, url: {}", message.chat.id, subscription.url)).await?;
 
-            log::info!("Subscription has been added: {:?}", subscription);
+            tracing::info!("Subscription has been added: {:?}", subscription);
         }
         Err(err) => {
             bot.send_message(UserId(sess_data.user_id as u64), format!("Failed to add subscription: {}", err)).await?;
-            log::error!("Failed to add subscription: {}", err);
+            tracing::error!("Failed to add subscription: {}", err);
             return Err(err.into());
         }
     };
@@ -117,10 +113,10 @@ pub async fn handle_list(bot: Bot, message: Message, subscription_service: Arc<s
             let user_data = user_data_map.get(&sub.user_refer);
             match user_data {
                 Some(user_data) => {
-                    format!("{}\nID {}: {} by <a href=\"tg://user?id={}\">{}</a>", acc, sub.id, sub.url, user_data.telegram_user_id, user_data.username)
+                    format!("{}\nID {}: {} by <a href=\"tg://user?id={}\">{}</a>", acc, sub.id, subscription_link(sub), user_data.telegram_user_id, user_data.username)
                 }
                 None => {
-                    format!("{}\nID {}: {} by unknown user", acc, sub.id, sub.url)
+                    format!("{}\nID {}: {} by unknown user", acc, sub.id, subscription_link(sub))
                 }
             }
         });
@@ -0,0 +1,134 @@
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::http::{header, StatusCode};
+use axum::routing::get;
+use axum::{Json, Router};
+use redis::aio::MultiplexedConnection;
+use sea_orm::DatabaseConnection;
+use tokio_util::sync::CancellationToken;
+
+use crate::scheduler;
+
+/// How long a single readiness check may take before it counts as failed.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long this node may go unseen in the node pool, which is polled every second, before
+/// the scheduler counts as stopped.
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Dependencies probed by the readiness endpoint.
+#[derive(Clone)]
+pub struct Probes {
+    pub db: DatabaseConnection,
+    /// Unset when Redis is not used.
+    pub redis_con: Option<MultiplexedConnection>,
+    /// When the scheduler last found this node in the node pool.
+    pub heartbeat: scheduler::Heartbeat,
+    pub shutdown: CancellationToken,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct Readiness {
+    ready: bool,
+    checks: Checks,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct Checks {
+    database: Check,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    redis: Option<Check>,
+    scheduler: Check,
+    shutdown: Check,
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(tag = "status", content = "error", rename_all = "snake_case")]
+enum Check {
+    Ok,
+    Failed(String),
+}
+
+impl Check {
+    fn is_ok(&self) -> bool {
+        matches!(self, Check::Ok)
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+struct BuildInfo {
+    name: &'static str,
+    version: &'static str,
+    commit: Option<&'static str>,
+}
+
+/// Routes for `/healthz`, `/readyz`, `/version` and `/metrics`.
+pub fn router(probes: Probes) -> Router {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/version", get(version))
+        .route("/metrics", get(metrics))
+        .with_state(probes)
+}
+
+/// Liveness: the process is up and serving requests.
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+/// Readiness: every dependency answers and the node is not shutting down.
+async fn readyz(State(probes): State<Probes>) -> (StatusCode, Json<Readiness>) {
+    let database = match tokio::time::timeout(CHECK_TIMEOUT, probes.db.ping()).await {
+        Ok(Ok(())) => Check::Ok,
+        Ok(Err(err)) => Check::Failed(err.to_string()),
+        Err(_) => Check::Failed("timed out".to_string()),
+    };
+
+    let redis = match probes.redis_con.clone() {
+        Some(mut redis_con) => {
+            let ping = redis::cmd("PING");
+            Some(match tokio::time::timeout(CHECK_TIMEOUT, ping.query_async::<_, String>(&mut redis_con)).await {
+                Ok(Ok(_)) => Check::Ok,
+                Ok(Err(err)) => Check::Failed(err.to_string()),
+                Err(_) => Check::Failed("timed out".to_string()),
+            })
+        }
+        None => None,
+    };
+
+    let scheduler = match probes.heartbeat.elapsed() {
+        Some(elapsed) if elapsed <= HEARTBEAT_TIMEOUT => Check::Ok,
+        Some(elapsed) => Check::Failed(format!("not in the node pool for {}s", elapsed.as_secs())),
+        None => Check::Failed("not in the node pool yet".to_string()),
+    };
+
+    let shutdown = if probes.shutdown.is_cancelled() {
+        Check::Failed("shutting down".to_string())
+    } else {
+        Check::Ok
+    };
+
+    let checks = Checks { database, redis, scheduler, shutdown };
+    let ready = checks.database.is_ok() && checks.redis.as_ref().is_none_or(Check::is_ok) && checks.scheduler.is_ok() && checks.shutdown.is_ok();
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+
+    (status, Json(Readiness { ready, checks }))
+}
+
+async fn version() -> Json<BuildInfo> {
+    Json(BuildInfo {
+        name: env!("CARGO_PKG_NAME"),
+        version: env!("CARGO_PKG_VERSION"),
+        commit: option_env!("GIT_COMMIT"),
+    })
+}
+
+/// Metrics in the Prometheus text exposition format.
+async fn metrics() -> ([(header::HeaderName, &'static str); 1], String) {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        rssbot_common::observability::metrics::metrics().encode(),
+    )
+}
@@ -0,0 +1,55 @@
+use std::sync::Arc;
+
+use reqwest::header::{HeaderName, HeaderValue};
+
+use rssbot_common::config::Config;
+
+/// Hosts that outbound requests carry the current trace to, with W3C `traceparent` and
+/// `tracestate` headers. Other hosts, e.g. the ones feeds are on, don't learn the trace IDs.
+#[derive(Debug, Clone, Default)]
+pub struct TracePropagation {
+    hosts: Arc<[String]>,
+}
+
+impl TracePropagation {
+    pub fn new(hosts: impl IntoIterator<Item = String>) -> Self {
+        Self { hosts: hosts.into_iter().collect() }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(config.otel_propagate_hosts.iter().cloned())
+    }
+
+    /// Add the trace headers to `request` if it goes to one of the hosts.
+    pub fn apply(&self, request: &mut reqwest::Request) {
+        if !self.allows(request.url()) {
+            return;
+        }
+
+        for (name, value) in rssbot_common::observability::tracing::trace_headers() {
+            if let (Ok(name), Ok(value)) = (HeaderName::try_from(name), HeaderValue::try_from(value)) {
+                request.headers_mut().insert(name, value);
+            }
+        }
+    }
+
+    fn allows(&self, url: &reqwest::Url) -> bool {
+        url.host_str().is_some_and(|host| self.hosts.iter().any(|allowed| allowed.eq_ignore_ascii_case(host)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn propagates_only_to_the_configured_hosts() {
+        let url = |url: &str| url.parse::<reqwest::Url>().unwrap();
+        let propagation = TracePropagation::new(["Feeds.internal".to_string()]);
+
+        assert!(propagation.allows(&url("http://feeds.internal:8080/blog.xml")));
+        assert!(!propagation.allows(&url("https://example.com/feed.xml")));
+        assert!(!propagation.allows(&url("https://feeds.internal.example.com/feed.xml")));
+        assert!(!TracePropagation::default().allows(&url("http://feeds.internal/blog.xml")));
+    }
+}
@@ -0,0 +1,13 @@
+pub mod cli;
+pub mod credentials;
+pub mod data;
+pub mod feed;
+pub mod fetch;
+pub mod dialogue;
+pub mod filters;
+pub mod handlers;
+pub mod health;
+pub mod http;
+pub mod scheduler;
+pub mod services;
+pub mod webhook;
@@ -1,23 +1,37 @@
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
+use clap::Parser;
 use distributed_scheduler::cron::Cron;
 use distributed_scheduler::driver::redis_zset::RedisZSetDriver;
 use distributed_scheduler::node_pool::NodePool;
 use sea_orm::Database;
-use teloxide::dispatching::dialogue::{RedisStorage, serializer};
 use teloxide::prelude::*;
-use teloxide::update_listeners::webhooks::Options;
+use teloxide::update_listeners::Polling;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
 
-mod handlers;
-mod services;
-mod filters;
-mod data;
+use rssbot_common::config::{StateBackend, UpdateSource};
+use rssbot_server::{cli, credentials, dialogue, feed, fetch, handlers, health, scheduler, services, webhook};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     dotenv::dotenv().ok();
 
-    let config = rssbot_common::config::Config::new()?;
+    let cli = cli::Cli::parse();
+    let config = match rssbot_common::config::Config::load(&cli.sources()) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(2);
+        }
+    };
+
+    if cli.print_config {
+        print!("{}", toml::to_string_pretty(&config.redacted())?);
+        return Ok(());
+    }
 
     // Initialize the tracer
     rssbot_common::observability::tracing::init_tracer(
@@ -26,97 +40,265 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         &config,
     );
 
-    pretty_env_logger::try_init().ok();
+    // cancelled on SIGINT/SIGTERM or when a component stops, tasks in the tracker are drained
+    let shutdown = CancellationToken::new();
+    let tasks = TaskTracker::new();
 
     let db = Database::connect(&config.database_url).await?;
-    let redis_client = redis::Client::open(config.redis_url.as_str())?;
+    if config.migrate_on_startup {
+        tracing::info!("Applying pending migrations");
+        rssbot_migrator::schema::migrate(&db).await?;
+    } else if let Err(err) = rssbot_migrator::schema::check(&db).await {
+        tracing::error!("{}", err);
+        tokio::task::spawn_blocking(rssbot_common::observability::tracing::shutdown_tracer).await.ok();
+        std::process::exit(2);
+    }
+    let redis_client = match config.state_backend {
+        StateBackend::Redis => Some(redis::Client::open(config.redis_url.as_str())?),
+        StateBackend::Local => None,
+    };
 
+    let heartbeat = scheduler::Heartbeat::default();
     let scheduler = {
         let node_id = uuid::Uuid::new_v4().to_string();
-        let driver = RedisZSetDriver::new(redis_client.clone(), env!("CARGO_PKG_NAME"), node_id.as_str()).await?;
-        let node_pool = NodePool::new(driver).await?;
+        let node_pool = match &redis_client {
+            Some(redis_client) => {
+                let driver = RedisZSetDriver::new(redis_client.clone(), env!("CARGO_PKG_NAME"), node_id.as_str()).await?;
+                NodePool::new(scheduler::MonitoredDriver::new(driver, heartbeat.clone())).await?
+            }
+            None => NodePool::new(scheduler::MonitoredDriver::new(scheduler::LocalDriver::new(node_id.as_str()), heartbeat.clone())).await?,
+        };
         Cron::new(node_pool).await
     };
 
+    let cache_service = Arc::new(match &redis_client {
+        Some(redis_client) => services::cache::Service::redis(redis_client.get_multiplexed_tokio_connection().await?),
+        None => services::cache::Service::local(),
+    });
+
     let bot = Bot::new(&config.bot_token)
         .set_api_url(config.api_server.parse()?);
 
-    let subscription_service = Arc::new(services::subscription::Service::new(db.clone(), bot.clone()));
+    let delivery_service = Arc::new(services::delivery::Service::new(
+        db.clone(),
+        bot.clone(),
+        services::delivery::Options::from_config(&config),
+    ));
+    let fetch_policy = fetch::Policy::from_config(&config)?;
+    let extractor_service = Arc::new(services::extractor::Service::new(
+        cache_service.clone(),
+        services::extractor::Options::from_config(&config),
+        &fetch_policy,
+    )?);
+    let telegraph_service = services::telegraph::Service::from_config(&config).map(Arc::new);
+    let subscription_service = Arc::new(services::subscription::Service::new(
+        db.clone(),
+        delivery_service.clone(),
+        extractor_service,
+        telegraph_service,
+        feed::Options::from_config(&config),
+        fetch_policy,
+        credentials::Cipher::from_config(&config)?,
+    )?.with_limits(services::subscription::Limits::from_config(&config)));
     let user_service = Arc::new(services::user::Service::new(db.clone()));
+    let admin_service = Arc::new(services::admin::Service::new(
+        db.clone(),
+        delivery_service.clone(),
+        config.admin_user_ids.iter().copied(),
+    ));
 
     scheduler.add_async_job(
         "sync_subscription",
         "0 * * * * *".parse()?,
         {
             let subscription_service = subscription_service.clone();
+            let shutdown = shutdown.clone();
+            let tasks = tasks.clone();
             move || {
                 let service = subscription_service.clone();
+                let shutdown = shutdown.clone();
+                let tasks = tasks.clone();
                 async move {
-                    service.sync_subscriptions().await?;
+                    if shutdown.is_cancelled() {
+                        return Ok(());
+                    }
+
+                    // run in the tracker so that a sync in progress is drained on shutdown
+                    tasks.spawn(async move { service.sync_subscriptions(&shutdown).await }).await??;
                     Ok(())
                 }
             }
         },
     ).await?;
 
-    let state_storage = RedisStorage::open(config.redis_url.as_str(), serializer::Json).await?;
-    let listener = teloxide::update_listeners::webhooks::axum(
-        bot.clone(),
-        Options::new(
-            config.webhook_address.parse()?,
-            config.webhook_url.parse()?,
-        ),
+    scheduler.add_async_job(
+        "purge_deliveries",
+        "0 0 * * * *".parse()?,
+        {
+            let delivery_service = delivery_service.clone();
+            move || {
+                let service = delivery_service.clone();
+                async move {
+                    service.purge_sent().await?;
+                    Ok(())
+                }
+            }
+        },
     ).await?;
-    let redis_con = redis_client.get_multiplexed_tokio_connection().await?;
-
-    let private_message_handlers = dptree::entry()
-        .filter(filters::private_message_only)
-        .branch(
-            Update::filter_message()
-                .enter_dialogue::<Message, RedisStorage<serializer::Json>, handlers::private::State>()
-                .branch(
-                    dptree::case![handlers::private::State::Unstated]
-                        .filter_command::<handlers::private::UnstatedCommand>()
-                        .branch(dptree::case![handlers::private::UnstatedCommand::Start].endpoint(handlers::private::handle_start))
-                        .branch(dptree::case![handlers::private::UnstatedCommand::Help].endpoint(handlers::private::handle_help))
-                        .branch(dptree::case![handlers::private::UnstatedCommand::Subscribe].endpoint(handlers::private::handle_subscribe_command))
-                        .branch(dptree::case![handlers::private::UnstatedCommand::List].endpoint(handlers::private::handle_list_command))
-                        .branch(dptree::case![handlers::private::UnstatedCommand::Unsubscribe].endpoint(handlers::private::handle_unsubscribe_command))
-                )
-                .branch(dptree::case![handlers::private::State::SubscribeWaitingUrl].endpoint(handlers::private::handle_subscribe_enter_url))
-        )
-        .branch(
-            Update::filter_callback_query()
-                .enter_dialogue::<CallbackQuery, RedisStorage<serializer::Json>, handlers::private::State>()
-                .branch(dptree::case![handlers::private::State::UnsubscribeWaitingCallbackQuery].endpoint(handlers::private::handle_unsubscribe_callback))
-        );
-
-    let channel_or_group_handlers = dptree::entry()
-        .filter(filters::channel_or_group)
-        .branch(
-            Update::filter_message()
-                .filter_command::<handlers::public::Command>()
-                .branch(dptree::case![handlers::public::Command::Start { id }].endpoint(handlers::public::handle_start))
-                .branch(dptree::case![handlers::public::Command::Help].endpoint(handlers::public::handle_unstated_help))
-                .branch(dptree::case![handlers::public::Command::List].endpoint(handlers::public::handle_list))
-        );
+
+    let state_storage: Arc<dialogue::DialogueStorage<handlers::private::State>> = match config.state_backend {
+        StateBackend::Redis => dialogue::DialogueStorage::redis(config.redis_url.as_str()).await?,
+        StateBackend::Local => dialogue::DialogueStorage::database(db.clone()),
+    };
+    let webhook_settings = match config.update_source {
+        UpdateSource::Webhook => Some(webhook::Settings::from_config(&config)?),
+        UpdateSource::Polling => None,
+    };
 
     let mut dispatcher = Dispatcher::builder(
-        bot,
-        dptree::entry()
-            .branch(channel_or_group_handlers)
-            .branch(private_message_handlers),
+        bot.clone(),
+        handlers::schema(),
     )
         .distribution_function(|_| None::<std::convert::Infallible>)
-        .dependencies(dptree::deps![state_storage, subscription_service, user_service, redis_con])
+        .dependencies(dptree::deps![state_storage, subscription_service, user_service, admin_service, cache_service])
+        .error_handler(Arc::new(handlers::handle_error))
         .build();
 
+    let health_routes = health::router(health::Probes {
+        db: db.clone(),
+        redis_con: match &redis_client {
+            Some(redis_client) => Some(redis_client.get_multiplexed_tokio_connection().await?),
+            None => None,
+        },
+        heartbeat,
+        shutdown: shutdown.clone(),
+    });
+
+    // serve health and metrics endpoints on their own listener if configured, next to the webhook otherwise
+    let health_routes = match &config.admin_address {
+        Some(address) => {
+            let address: SocketAddr = address.parse()?;
+            let server = axum::Server::try_bind(&address)?
+                .serve(health_routes.into_make_service())
+                .with_graceful_shutdown(shutdown.clone().cancelled_owned());
+            tasks.spawn(async move {
+                if let Err(err) = server.await {
+                    tracing::error!("Admin server error: {}", err);
+                }
+            });
+
+            tracing::info!("Serving health and metrics endpoints on {}", address);
+            None
+        }
+        None if webhook_settings.is_none() => {
+            tracing::warn!("Health and metrics endpoints are disabled with long polling unless ADMIN_ADDRESS is set");
+            None
+        }
+        None => Some(health_routes),
+    };
+
+    let dispatcher_shutdown = dispatcher.shutdown_token();
+    // fails when no updates can be received at all
+    let dispatcher_task = tasks.spawn({
+        let bot = bot.clone();
+        let shutdown = shutdown.clone();
+        async move {
+            match webhook_settings {
+                Some(settings) => match webhook::listen(bot, settings, health_routes).await {
+                    Ok(listener) => dispatcher.dispatch_with_listener(listener, LoggingErrorHandler::new()).await,
+                    Err(err) => {
+                        tracing::error!("Failed to start webhook listener: {}", err);
+                        shutdown.cancel();
+                        return Err(err);
+                    }
+                },
+                None => {
+                    // a webhook left over from a previous deployment would make `getUpdates` fail
+                    tracing::info!("Receiving updates with long polling, removing the webhook if set");
+                    let listener = Polling::builder(bot)
+                        .timeout(Duration::from_secs(10))
+                        .delete_webhook()
+                        .await
+                        .build();
+                    dispatcher.dispatch_with_listener(listener, LoggingErrorHandler::new()).await;
+                }
+            }
+
+            tracing::info!("Dispatcher stopped");
+            shutdown.cancel();
+            Ok(())
+        }
+    });
+
+    tasks.spawn({
+        let delivery_service = delivery_service.clone();
+        let shutdown = shutdown.clone();
+        async move { delivery_service.run(shutdown).await }
+    });
+
     tokio::select! {
-        _ = scheduler.start() => {}
-        _ = dispatcher.dispatch_with_listener(listener, LoggingErrorHandler::new()) => {}
+        _ = scheduler.start() => tracing::error!("Scheduler stopped"),
+        _ = shutdown_signal() => tracing::info!("Shutdown signal received"),
+        _ = shutdown.cancelled() => {}
     }
 
+    // stop accepting work, then give in-flight syncs, deliveries and handlers time to finish
+    tracing::info!("Shutting down, draining in-flight work for up to {}s", config.shutdown_timeout_secs);
+    shutdown.cancel();
+    tasks.close();
+
+    let drain = async {
+        if let Ok(stopped) = dispatcher_shutdown.shutdown() {
+            stopped.await;
+        }
+        tasks.wait().await;
+    };
+    if tokio::time::timeout(Duration::from_secs(config.shutdown_timeout_secs), drain).await.is_err() {
+        tracing::warn!("Timed out draining in-flight work");
+    }
+
+    let failure = match dispatcher_task.is_finished() {
+        true => dispatcher_task.await?.err(),
+        false => None,
+    };
+
     db.close().await?;
 
-    Ok(())
+    tokio::task::spawn_blocking(rssbot_common::observability::tracing::shutdown_tracer).await?;
+
+    match failure {
+        Some(err) => Err(err.into()),
+        None => Ok(()),
+    }
+}
+
+/// Resolve on SIGINT or, on Unix, SIGTERM.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            tracing::error!("Failed to listen for SIGINT: {}", err);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(err) => {
+                tracing::error!("Failed to listen for SIGTERM: {}", err);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
 }
@@ -0,0 +1,108 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use distributed_scheduler::driver::Driver;
+
+/// Scheduler driver for a single node: the pool only ever contains this node, so it
+/// runs every job.
+#[derive(Debug, Clone)]
+pub struct LocalDriver {
+    node_id: String,
+}
+
+impl LocalDriver {
+    pub fn new(node_id: &str) -> Self {
+        Self { node_id: node_id.to_string() }
+    }
+}
+
+#[async_trait::async_trait]
+impl Driver for LocalDriver {
+    fn node_id(&self) -> String {
+        self.node_id.clone()
+    }
+
+    async fn get_nodes(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        Ok(vec![self.node_id.clone()])
+    }
+}
+
+/// When the node pool last found this node among the nodes, shared with the readiness check.
+#[derive(Debug, Clone, Default)]
+pub struct Heartbeat(Arc<Mutex<Option<Instant>>>);
+
+impl Heartbeat {
+    /// Time since this node was last found in the pool, `None` if it never was.
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.0.lock().unwrap().map(|seen| seen.elapsed())
+    }
+
+    fn beat(&self) {
+        *self.0.lock().unwrap() = Some(Instant::now());
+    }
+}
+
+/// Driver recording a [`Heartbeat`] whenever the node pool, which polls the nodes every
+/// second while the scheduler runs, finds this node among them.
+#[derive(Debug)]
+pub struct MonitoredDriver<D> {
+    inner: D,
+    heartbeat: Heartbeat,
+}
+
+impl<D: Driver> MonitoredDriver<D> {
+    pub fn new(inner: D, heartbeat: Heartbeat) -> Self {
+        Self { inner, heartbeat }
+    }
+}
+
+#[async_trait::async_trait]
+impl<D: Driver> Driver for MonitoredDriver<D> {
+    fn node_id(&self) -> String {
+        self.inner.node_id()
+    }
+
+    async fn get_nodes(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let nodes = self.inner.get_nodes().await?;
+        if nodes.contains(&self.inner.node_id()) {
+            self.heartbeat.beat();
+        }
+
+        Ok(nodes)
+    }
+
+    async fn start(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.inner.start().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Driver of a pool this node has dropped out of.
+    #[derive(Debug)]
+    struct OtherNodes;
+
+    #[async_trait::async_trait]
+    impl Driver for OtherNodes {
+        fn node_id(&self) -> String {
+            "this".to_string()
+        }
+
+        async fn get_nodes(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+            Ok(vec!["other".to_string()])
+        }
+    }
+
+    #[tokio::test]
+    async fn beats_only_while_in_the_pool() {
+        let heartbeat = Heartbeat::default();
+
+        MonitoredDriver::new(OtherNodes, heartbeat.clone()).get_nodes().await.unwrap();
+        assert_eq!(heartbeat.elapsed(), None);
+
+        MonitoredDriver::new(LocalDriver::new("this"), heartbeat.clone()).get_nodes().await.unwrap();
+        assert!(heartbeat.elapsed().is_some_and(|elapsed| elapsed < Duration::from_secs(1)));
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use sea_orm::QuerySelect;
+use sea_orm::prelude::*;
+
+use rssbot_entities::delivery::Status;
+use rssbot_entities::{disabled_feed, subscription, user};
+
+use crate::services::delivery;
+
+/// Operations across every user, for the configured administrators.
+#[derive(Debug)]
+pub struct Service {
+    db: DatabaseConnection,
+    delivery: Arc<delivery::Service>,
+    admins: HashSet<i64>,
+}
+
+/// Counts across every user.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub users: u64,
+    pub banned_users: u64,
+    pub subscriptions: u64,
+    /// Distinct feed URLs.
+    pub feeds: u64,
+    pub disabled_feeds: u64,
+    /// Subscriptions whose last sync failed.
+    pub failing_subscriptions: u64,
+    pub pending_deliveries: u64,
+    pub failed_deliveries: u64,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Database error: {0}")]
+    Database(#[from] DbErr),
+    #[error("Delivery error: {0}")]
+    Delivery(#[from] delivery::Error),
+}
+
+impl Service {
+    pub fn new(db: DatabaseConnection, delivery: Arc<delivery::Service>, admins: impl IntoIterator<Item = i64>) -> Self {
+        Self { db, delivery, admins: admins.into_iter().collect() }
+    }
+
+    pub fn is_admin(&self, user_id: i64) -> bool {
+        self.admins.contains(&user_id)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn stats(&self) -> Result<Stats, Error> {
+        Ok(Stats {
+            users: user::Entity::find().count(&self.db).await?,
+            banned_users: user::Entity::find()
+                .filter(user::Column::Banned.eq(true))
+                .count(&self.db)
+                .await?,
+            subscriptions: subscription::Entity::find().count(&self.db).await?,
+            feeds: subscription::Entity::find()
+                .select_only()
+                .column(subscription::Column::Url)
+                .distinct()
+                .count(&self.db)
+                .await?,
+            disabled_feeds: disabled_feed::Entity::find().count(&self.db).await?,
+            failing_subscriptions: subscription::Entity::find()
+                .filter(subscription::Column::LastError.is_not_null())
+                .count(&self.db)
+                .await?,
+            pending_deliveries: self.delivery.count(Status::Pending).await?,
+            failed_deliveries: self.delivery.count(Status::Failed).await?,
+        })
+    }
+
+    /// Queue `text` for every owner of a subscription who is not banned, returning how many
+    /// there are. Messages go through the delivery queue like feed updates.
+    #[tracing::instrument(skip(self, text))]
+    pub async fn broadcast(&self, text: &str) -> Result<usize, Error> {
+        let owners: Vec<i64> = subscription::Entity::find()
+            .select_only()
+            .column(subscription::Column::UserRefer)
+            .distinct()
+            .into_tuple()
+            .all(&self.db)
+            .await?;
+        let banned: HashSet<i64> = user::Entity::find()
+            .filter(user::Column::Banned.eq(true))
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(|user| user.telegram_user_id)
+            .collect();
+
+        let mut queued = 0;
+        for owner in owners.into_iter().filter(|owner| !banned.contains(owner)) {
+            self.delivery.enqueue(delivery::NewDelivery {
+                subscription_id: None,
+                target_chat: owner,
+                text: text.to_string(),
+                parse_mode: None,
+                button: None,
+            }).await?;
+            queued += 1;
+        }
+
+        tracing::info!("Broadcast queued for {} users", queued);
+
+        Ok(queued)
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use redis::aio::MultiplexedConnection;
+use redis::AsyncCommands;
+
+/// Short-lived key-value store for caches and sessions.
+///
+/// Backed by Redis when nodes share state, or by process memory on a single node.
+pub struct Service {
+    backend: Backend,
+}
+
+enum Backend {
+    Redis(MultiplexedConnection),
+    Local(Mutex<HashMap<String, (String, Instant)>>),
+}
+
+impl std::fmt::Debug for Service {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let backend = match self.backend {
+            Backend::Redis(_) => "redis",
+            Backend::Local(_) => "local",
+        };
+
+        f.debug_struct("Service")
+            .field("backend", &backend)
+            .finish()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Redis error: {0}")]
+    Redis(#[from] redis::RedisError),
+}
+
+impl Service {
+    pub fn redis(redis_con: MultiplexedConnection) -> Self {
+        Self { backend: Backend::Redis(redis_con) }
+    }
+
+    pub fn local() -> Self {
+        Self { backend: Backend::Local(Mutex::new(HashMap::new())) }
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn get(&self, key: &str) -> Result<Option<String>, Error> {
+        match &self.backend {
+            Backend::Redis(redis_con) => Ok(redis_con.clone().get(key).await?),
+            Backend::Local(entries) => {
+                let entries = entries.lock().unwrap();
+                Ok(entries.get(key)
+                    .filter(|(_, expires_at)| *expires_at > Instant::now())
+                    .map(|(value, _)| value.clone()))
+            }
+        }
+    }
+
+    /// Store `value` under `key` for `ttl` seconds.
+    #[tracing::instrument(skip(self, value))]
+    pub async fn set_ex(&self, key: &str, value: &str, ttl: u64) -> Result<(), Error> {
+        match &self.backend {
+            Backend::Redis(redis_con) => Ok(redis_con.clone().set_ex(key, value, ttl).await?),
+            Backend::Local(entries) => {
+                let now = Instant::now();
+                let mut entries = entries.lock().unwrap();
+                // nothing else removes expired entries
+                entries.retain(|_, (_, expires_at)| *expires_at > now);
+                entries.insert(key.to_string(), (value.to_string(), now + Duration::from_secs(ttl)));
+                Ok(())
+            }
+        }
+    }
+
+    /// Remove `key` and return its value, so that a session can only be used once.
+    #[tracing::instrument(skip(self))]
+    pub async fn take(&self, key: &str) -> Result<Option<String>, Error> {
+        match &self.backend {
+            // in one command, so that concurrent callers can't both get the value
+            Backend::Redis(redis_con) => Ok(redis_con.clone().get_del(key).await?),
+            Backend::Local(entries) => {
+                let mut entries = entries.lock().unwrap();
+                Ok(entries.remove(key)
+                    .filter(|(_, expires_at)| *expires_at > Instant::now())
+                    .map(|(value, _)| value))
+            }
+        }
+    }
+}
@@ -0,0 +1,579 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use sea_orm::{ActiveValue, QueryOrder, QuerySelect, TransactionTrait};
+use sea_orm::prelude::*;
+use sea_orm::sea_query::{LockBehavior, LockType};
+use teloxide::prelude::*;
+use teloxide::RequestError;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, ParseMode, ReplyMarkup};
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
+
+use rssbot_common::config::Config;
+use rssbot_common::observability::metrics::metrics;
+use rssbot_entities::{delivery, subscription};
+
+/// Persisted outbound message queue.
+///
+/// Messages are written to the `deliveries` table by [`Service::enqueue`] and sent by
+/// [`Service::run`], which respects Telegram's rate limits, honours `RetryAfter`,
+/// retries transient failures with backoff and records permanent failures. Sent messages
+/// are removed by [`Service::purge_sent`] once they are older than the retention.
+#[derive(Debug)]
+pub struct Service {
+    db: DatabaseConnection,
+    bot: Bot,
+    options: Options,
+    limiter: RateLimiter,
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Maximum number of messages sent per second across all chats.
+    pub global_rate: u32,
+    /// Minimum interval between two messages to the same private chat.
+    pub private_chat_interval: Duration,
+    /// Minimum interval between two messages to the same group or channel.
+    pub group_chat_interval: Duration,
+    /// Number of attempts before a transient failure becomes permanent.
+    pub max_attempts: i32,
+    /// How long the dispatcher sleeps when the queue has nothing due.
+    pub poll_interval: Duration,
+    /// Maximum number of deliveries claimed per dispatch round.
+    pub batch_size: u64,
+    /// How long sent deliveries are kept for, `None` to keep them forever.
+    pub retention: Option<Duration>,
+}
+
+impl Options {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            global_rate: config.delivery_global_rate.max(1),
+            private_chat_interval: Duration::from_millis(config.delivery_private_chat_interval_ms),
+            group_chat_interval: Duration::from_millis(config.delivery_group_chat_interval_ms),
+            max_attempts: config.delivery_max_attempts.max(1),
+            poll_interval: Duration::from_millis(config.delivery_poll_interval_ms),
+            batch_size: config.delivery_batch_size.max(1),
+            retention: (config.delivery_retention_days > 0)
+                .then(|| Duration::from_secs(config.delivery_retention_days * 24 * 60 * 60)),
+        }
+    }
+}
+
+/// How long a delivery stays claimed by a dispatcher. Deliveries it did not record by then, e.g.
+/// because the node crashed while sending, are claimed again.
+const CLAIM_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// How often the queue depth gauge is refreshed, counting scans every pending delivery.
+const QUEUE_DEPTH_INTERVAL: Duration = Duration::from_secs(15);
+
+/// A message to be queued for delivery.
+#[derive(Debug, Clone)]
+pub struct NewDelivery {
+    pub subscription_id: Option<i32>,
+    pub target_chat: i64,
+    pub text: String,
+    pub parse_mode: Option<ParseMode>,
+    /// Optional inline "url" button attached to the message, as `(text, url)`.
+    pub button: Option<(String, String)>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Database error: {0}")]
+    Database(#[from] DbErr),
+}
+
+/// What to do with a delivery after an attempt to send it.
+#[derive(Debug)]
+enum Outcome {
+    Sent,
+    Retry { after: Duration, error: String, count_attempt: bool },
+    Migrated(i64),
+    Failed(String),
+}
+
+impl Service {
+    pub fn new(db: DatabaseConnection, bot: Bot, options: Options) -> Self {
+        let limiter = RateLimiter::new(&options);
+        Self { db, bot, options, limiter }
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn enqueue(&self, new: NewDelivery) -> Result<delivery::Model, Error> {
+        let (button_text, button_url) = match new.button {
+            Some((text, url)) => (Some(text), Some(url)),
+            None => (None, None),
+        };
+
+        let now = chrono::Utc::now().naive_utc();
+        let delivery = delivery::ActiveModel {
+            subscription_id: ActiveValue::Set(new.subscription_id),
+            target_chat: ActiveValue::Set(new.target_chat),
+            text: ActiveValue::Set(new.text),
+            parse_mode: ActiveValue::Set(new.parse_mode.map(|mode| parse_mode_to_str(mode).to_string())),
+            button_text: ActiveValue::Set(button_text),
+            button_url: ActiveValue::Set(button_url),
+            status: ActiveValue::Set(delivery::Status::Pending),
+            attempts: ActiveValue::Set(0),
+            next_attempt_at: ActiveValue::Set(now),
+            sent_at: ActiveValue::Set(None),
+            last_error: ActiveValue::Set(None),
+            ..Default::default()
+        }
+            .insert(&self.db)
+            .await?;
+
+        tracing::debug!("Delivery enqueued: {}", delivery.id);
+
+        Ok(delivery)
+    }
+
+    /// Run the dispatcher until `shutdown` is cancelled.
+    ///
+    /// The attempt in progress is recorded before returning, the rest of the batch is released.
+    pub async fn run(&self, shutdown: CancellationToken) {
+        tracing::info!("Delivery dispatcher started");
+
+        let mut depth_updated: Option<Instant> = None;
+        while !shutdown.is_cancelled() {
+            let idle = match self.dispatch_due(&shutdown).await {
+                Ok(0) => true,
+                Ok(sent) => {
+                    tracing::debug!("Dispatched {} deliveries", sent);
+                    false
+                }
+                Err(err) => {
+                    tracing::error!("Failed to dispatch deliveries: {}", err);
+                    true
+                }
+            };
+
+            if depth_updated.is_none_or(|updated| updated.elapsed() >= QUEUE_DEPTH_INTERVAL) {
+                self.update_queue_depth().await;
+                depth_updated = Some(Instant::now());
+            }
+
+            if idle {
+                tokio::select! {
+                    _ = tokio::time::sleep(self.options.poll_interval) => {}
+                    _ = shutdown.cancelled() => {}
+                }
+            }
+        }
+
+        tracing::info!("Delivery dispatcher stopped");
+    }
+
+    /// Number of deliveries with `status`.
+    pub async fn count(&self, status: delivery::Status) -> Result<u64, Error> {
+        Ok(delivery::Entity::find()
+            .filter(delivery::Column::Status.eq(status))
+            .count(&self.db)
+            .await?)
+    }
+
+    /// Delete deliveries sent longer ago than the retention, failed ones are kept for inspection.
+    ///
+    /// Returns the number of deleted deliveries.
+    #[tracing::instrument(skip(self))]
+    pub async fn purge_sent(&self) -> Result<u64, Error> {
+        let now = chrono::Utc::now().naive_utc();
+        let sent_before = self.options.retention
+            .and_then(|retention| chrono::Duration::from_std(retention).ok())
+            .and_then(|retention| now.checked_sub_signed(retention));
+        let Some(sent_before) = sent_before else {
+            return Ok(0);
+        };
+
+        let result = delivery::Entity::delete_many()
+            .filter(delivery::Column::Status.eq(delivery::Status::Sent))
+            .filter(delivery::Column::SentAt.lt(sent_before))
+            .exec(&self.db)
+            .await?;
+
+        tracing::info!("Purged {} deliveries sent before {}", result.rows_affected, sent_before);
+
+        Ok(result.rows_affected)
+    }
+
+    async fn update_queue_depth(&self) {
+        match self.count(delivery::Status::Pending).await {
+            Ok(pending) => metrics().delivery_queue_depth.set(pending as i64),
+            Err(err) => tracing::warn!("Failed to count pending deliveries: {}", err),
+        }
+    }
+
+    /// Claim a batch of due deliveries and try to send them.
+    ///
+    /// Each attempt is recorded on its own once Telegram answered, so a failing write can't
+    /// return messages that were already sent to the queue.
+    /// Returns the number of deliveries that were attempted.
+    async fn dispatch_due(&self, shutdown: &CancellationToken) -> Result<usize, Error> {
+        let claimed = self.claim_due().await?;
+
+        // chats that must not receive anything else in this round, to keep messages in order
+        let mut held_chats = HashSet::new();
+        let mut attempted = 0;
+        let mut unattempted = Vec::new();
+
+        let mut claimed = claimed.into_iter();
+        for delivery in claimed.by_ref() {
+            if shutdown.is_cancelled() {
+                unattempted.push(delivery);
+                break;
+            }
+
+            if held_chats.contains(&delivery.target_chat) {
+                unattempted.push(delivery);
+                continue;
+            }
+
+            if self.limiter.try_acquire_chat(delivery.target_chat).is_some() {
+                held_chats.insert(delivery.target_chat);
+                unattempted.push(delivery);
+                continue;
+            }
+
+            self.limiter.acquire_global().await;
+
+            let outcome = self.send(&delivery).instrument(delivery_span(&delivery)).await;
+            if !matches!(outcome, Outcome::Sent) {
+                held_chats.insert(delivery.target_chat);
+            }
+
+            if let Err(err) = self.record(delivery, outcome).await {
+                // claimed again once the claim expires, a sent message may then be sent twice
+                tracing::error!("Failed to record a delivery attempt: {}", err);
+            }
+            attempted += 1;
+        }
+
+        unattempted.extend(claimed);
+        for delivery in unattempted {
+            self.release(delivery).await?;
+        }
+
+        Ok(attempted)
+    }
+
+    /// Mark a batch of due deliveries as being sent by this dispatcher.
+    ///
+    /// Rows are locked with `FOR UPDATE SKIP LOCKED` only while they are claimed, so several
+    /// nodes can run the dispatcher against the same database without sending a message twice.
+    /// Claims that expired count as due. Chats the rate limiter holds back are left out, so that
+    /// a backlog for one chat can't fill every batch while the others wait.
+    async fn claim_due(&self) -> Result<Vec<delivery::Model>, Error> {
+        let now = chrono::Utc::now().naive_utc();
+        let txn = self.db.begin().await?;
+
+        let mut query = delivery::Entity::find()
+            .filter(delivery::Column::Status.is_in([delivery::Status::Pending, delivery::Status::Sending]))
+            .filter(delivery::Column::NextAttemptAt.lte(now));
+        let waiting_chats = self.limiter.waiting_chats();
+        if !waiting_chats.is_empty() {
+            query = query.filter(delivery::Column::TargetChat.is_not_in(waiting_chats));
+        }
+
+        let due = query
+            .order_by_asc(delivery::Column::NextAttemptAt)
+            .order_by_asc(delivery::Column::Id)
+            .limit(self.options.batch_size)
+            .lock_with_behavior(LockType::Update, LockBehavior::SkipLocked)
+            .all(&txn)
+            .await?;
+
+        let claimed_until = now + chrono::Duration::from_std(CLAIM_TIMEOUT).unwrap_or(chrono::Duration::zero());
+        for delivery in &due {
+            if delivery.status == delivery::Status::Sending {
+                tracing::warn!("Claim of delivery {} expired, claiming it again", delivery.id);
+            }
+
+            delivery::Entity::update_many()
+                .col_expr(delivery::Column::Status, Expr::value(delivery::Status::Sending))
+                .col_expr(delivery::Column::NextAttemptAt, Expr::value(claimed_until))
+                .filter(delivery::Column::Id.eq(delivery.id))
+                .exec(&txn)
+                .await?;
+        }
+
+        txn.commit().await?;
+
+        Ok(due)
+    }
+
+    /// Put a claimed delivery back in the queue, in its place.
+    async fn release(&self, delivery: delivery::Model) -> Result<(), Error> {
+        delivery::Entity::update_many()
+            .col_expr(delivery::Column::Status, Expr::value(delivery::Status::Pending))
+            .col_expr(delivery::Column::NextAttemptAt, Expr::value(delivery.next_attempt_at))
+            .filter(delivery::Column::Id.eq(delivery.id))
+            .filter(delivery::Column::Status.eq(delivery::Status::Sending))
+            .exec(&self.db)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Send a delivery, in the span created by [`delivery_span`].
+    ///
+    /// The Telegram client doesn't allow per-request headers, so unlike other outbound
+    /// requests the trace context is not propagated to the Bot API.
+    async fn send(&self, delivery: &delivery::Model) -> Outcome {
+        let mut request = self.bot.send_message(ChatId(delivery.target_chat), delivery.text.clone());
+
+        if let Some(mode) = delivery.parse_mode.as_deref().and_then(|mode| ParseMode::try_from(mode).ok()) {
+            request = request.parse_mode(mode);
+        }
+
+        if let (Some(text), Some(url)) = (&delivery.button_text, &delivery.button_url) {
+            match url.parse() {
+                Ok(url) => {
+                    request = request.reply_markup(ReplyMarkup::InlineKeyboard(InlineKeyboardMarkup::new(vec![
+                        vec![InlineKeyboardButton::url(text.clone(), url)],
+                    ])));
+                }
+                Err(err) => {
+                    tracing::Span::current().record("otel.status_code", "ERROR").record("error.type", "invalid_button");
+                    metrics().messages_failed.with_label_values(&["invalid_button", "failed"]).inc();
+                    return Outcome::Failed(format!("Invalid button URL: {}", err));
+                }
+            }
+        }
+
+        let err = match request.send().await {
+            Ok(_) => {
+                metrics().messages_sent.inc();
+                return Outcome::Sent;
+            }
+            Err(err) => err,
+        };
+
+        let kind = error_kind(&err);
+        tracing::Span::current().record("otel.status_code", "ERROR").record("error.type", kind);
+        let outcome = match err {
+            RequestError::RetryAfter(after) => {
+                self.limiter.pause_chat(delivery.target_chat, after);
+                Outcome::Retry {
+                    after,
+                    error: RequestError::RetryAfter(after).to_string(),
+                    count_attempt: false,
+                }
+            }
+            RequestError::MigrateToChatId(chat_id) => Outcome::Migrated(chat_id),
+            err @ (RequestError::Network(_) | RequestError::Io(_)) => {
+                if delivery.attempts + 1 >= self.options.max_attempts {
+                    Outcome::Failed(err.to_string())
+                } else {
+                    Outcome::Retry {
+                        after: backoff(delivery.attempts + 1),
+                        error: err.to_string(),
+                        count_attempt: true,
+                    }
+                }
+            }
+            err => Outcome::Failed(err.to_string()),
+        };
+
+        let next = match outcome {
+            Outcome::Sent => "sent",
+            Outcome::Retry { .. } => "retry",
+            Outcome::Migrated(_) => "migrated",
+            Outcome::Failed(_) => "failed",
+        };
+        metrics().messages_failed.with_label_values(&[kind, next]).inc();
+
+        outcome
+    }
+
+    async fn record(&self, delivery: delivery::Model, outcome: Outcome) -> Result<(), Error> {
+        let now = chrono::Utc::now().naive_utc();
+        let txn = self.db.begin().await?;
+        let id = delivery.id;
+        let subscription_id = delivery.subscription_id;
+        let attempts = delivery.attempts;
+
+        let mut act: delivery::ActiveModel = delivery.into();
+        match outcome {
+            Outcome::Sent => {
+                act.status = ActiveValue::Set(delivery::Status::Sent);
+                act.attempts = ActiveValue::Set(attempts + 1);
+                act.sent_at = ActiveValue::Set(Some(now));
+                act.last_error = ActiveValue::Set(None);
+            }
+            Outcome::Retry { after, error, count_attempt } => {
+                tracing::warn!("Delivery {} will be retried in {:?}: {}", id, after, error);
+
+                if count_attempt {
+                    act.attempts = ActiveValue::Set(attempts + 1);
+                }
+                act.status = ActiveValue::Set(delivery::Status::Pending);
+                act.next_attempt_at = ActiveValue::Set(now + chrono::Duration::from_std(after).unwrap_or(chrono::Duration::zero()));
+                act.last_error = ActiveValue::Set(Some(error));
+            }
+            Outcome::Migrated(chat_id) => {
+                tracing::info!("Delivery {} target chat migrated to {}", id, chat_id);
+
+                // later items go to the new chat right away
+                if let Some(subscription_id) = subscription_id {
+                    subscription::Entity::update_many()
+                        .col_expr(subscription::Column::TargetChat, Expr::value(chat_id))
+                        .filter(subscription::Column::Id.eq(subscription_id))
+                        .exec(&txn)
+                        .await?;
+                }
+
+                // back in its place in the queue
+                act.status = ActiveValue::Set(delivery::Status::Pending);
+                act.reset(delivery::Column::NextAttemptAt);
+                act.target_chat = ActiveValue::Set(chat_id);
+            }
+            Outcome::Failed(error) => {
+                tracing::error!("Delivery {} failed permanently: {}", id, error);
+
+                if let Some(subscription_id) = subscription_id {
+                    subscription::Entity::update_many()
+                        .col_expr(subscription::Column::LastError, Expr::value(format!("Delivery failed: {}", error)))
+                        .filter(subscription::Column::Id.eq(subscription_id))
+                        .exec(&txn)
+                        .await?;
+                }
+
+                act.status = ActiveValue::Set(delivery::Status::Failed);
+                act.attempts = ActiveValue::Set(attempts + 1);
+                act.last_error = ActiveValue::Set(Some(error));
+            }
+        }
+
+        act.update(&txn).await?;
+        txn.commit().await?;
+
+        Ok(())
+    }
+}
+
+/// Root span of a delivery attempt.
+fn delivery_span(delivery: &delivery::Model) -> tracing::Span {
+    tracing::info_span!(
+        parent: None,
+        "delivery",
+        otel.kind = "client",
+        otel.status_code = tracing::field::Empty,
+        delivery.id = delivery.id,
+        delivery.attempt = delivery.attempts + 1,
+        telegram.chat_id = delivery.target_chat,
+        "error.type" = tracing::field::Empty,
+    )
+}
+
+/// Exponential backoff for transient failures, starting at 10 seconds and capped at one hour.
+fn backoff(attempts: i32) -> Duration {
+    let exp = attempts.clamp(1, 16) as u32 - 1;
+    Duration::from_secs(10u64.saturating_mul(1 << exp)).min(Duration::from_secs(60 * 60))
+}
+
+/// Metric label for a failed request.
+fn error_kind(err: &RequestError) -> &'static str {
+    match err {
+        RequestError::Api(_) => "api",
+        RequestError::MigrateToChatId(_) => "migrate_to_chat_id",
+        RequestError::RetryAfter(_) => "retry_after",
+        RequestError::Network(_) => "network",
+        RequestError::InvalidJson { .. } => "invalid_json",
+        RequestError::Io(_) => "io",
+    }
+}
+
+fn parse_mode_to_str(mode: ParseMode) -> &'static str {
+    #[allow(deprecated)]
+    match mode {
+        ParseMode::MarkdownV2 => "markdownv2",
+        ParseMode::Html => "html",
+        ParseMode::Markdown => "markdown",
+    }
+}
+
+/// In-process rate limiter following Telegram's documented limits: a global rate
+/// across all chats, and a minimum interval per chat which is longer for groups
+/// and channels (negative chat IDs) than for private chats.
+#[derive(Debug)]
+struct RateLimiter {
+    global_interval: Duration,
+    private_chat_interval: Duration,
+    group_chat_interval: Duration,
+    state: Mutex<RateLimiterState>,
+}
+
+#[derive(Debug)]
+struct RateLimiterState {
+    global_next: Instant,
+    chat_next: HashMap<i64, Instant>,
+}
+
+impl RateLimiter {
+    fn new(options: &Options) -> Self {
+        Self {
+            global_interval: Duration::from_secs(1) / options.global_rate,
+            private_chat_interval: options.private_chat_interval,
+            group_chat_interval: options.group_chat_interval,
+            state: Mutex::new(RateLimiterState {
+                global_next: Instant::now(),
+                chat_next: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Reserve a slot for the chat if it is available now, otherwise return how long to wait.
+    fn try_acquire_chat(&self, chat_id: i64) -> Option<Duration> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(next) = state.chat_next.get(&chat_id) {
+            if *next > now {
+                return Some(*next - now);
+            }
+        }
+
+        let interval = if chat_id < 0 { self.group_chat_interval } else { self.private_chat_interval };
+        state.chat_next.insert(chat_id, now + interval);
+
+        if state.chat_next.len() > 10_000 {
+            state.chat_next.retain(|_, next| *next > now);
+        }
+
+        None
+    }
+
+    /// Chats that have to wait for their next slot.
+    fn waiting_chats(&self) -> Vec<i64> {
+        let now = Instant::now();
+        let state = self.state.lock().unwrap();
+
+        state.chat_next.iter()
+            .filter(|(_, next)| **next > now)
+            .map(|(chat_id, _)| *chat_id)
+            .collect()
+    }
+
+    /// Wait for the next global slot.
+    async fn acquire_global(&self) {
+        let wait = {
+            let now = Instant::now();
+            let mut state = self.state.lock().unwrap();
+            let slot = state.global_next.max(now);
+            state.global_next = slot + self.global_interval;
+            slot - now
+        };
+
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Block a chat for the given duration, e.g. after a `RetryAfter` error.
+    fn pause_chat(&self, chat_id: i64, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.chat_next.insert(chat_id, Instant::now() + duration);
+    }
+}
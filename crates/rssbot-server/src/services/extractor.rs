@@ -0,0 +1,319 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use scraper::{ElementRef, Html, Selector};
+
+use rssbot_common::config::Config;
+
+use crate::fetch;
+use crate::services::cache;
+
+/// Full-text extraction for summary-only feeds.
+///
+/// Downloads the article behind an item link and extracts its main content with a
+/// readability-style scoring of paragraphs. Results are cached.
+#[derive(Clone)]
+pub struct Service {
+    client: fetch::Client,
+    cache: Arc<cache::Service>,
+    options: Options,
+}
+
+impl std::fmt::Debug for Service {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Service")
+            .field("options", &self.options)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Maximum size of a downloaded page.
+    pub max_bytes: usize,
+    /// Maximum number of characters kept from the extracted content.
+    pub max_chars: usize,
+    /// How long extracted content is cached.
+    pub cache_ttl: u64,
+    /// Timeout of a whole page download.
+    pub timeout: Duration,
+}
+
+impl Options {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            max_bytes: config.full_text_max_bytes,
+            max_chars: config.full_text_max_chars,
+            cache_ttl: config.full_text_cache_ttl,
+            timeout: Duration::from_millis(config.full_text_timeout_ms),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Failed to fetch page: {0}")]
+    Fetch(#[from] fetch::Error),
+    #[error("Response status is not OK: {0}")]
+    ResponseStatusNotOk(reqwest::StatusCode),
+    #[error("Invalid URL: {0}")]
+    InvalidUrl(String),
+    #[error("Page is not HTML: {0}")]
+    NotHtml(String),
+    #[error("Cache error: {0}")]
+    Cache(#[from] cache::Error),
+}
+
+const CACHE_KEY_PREFIX: &str = "rssbot:full_text:";
+
+impl Service {
+    /// Pages are fetched within `fetch`, with the size and time limits of `options`.
+    pub fn new(cache: Arc<cache::Service>, options: Options, fetch: &fetch::Policy) -> Result<Self, Error> {
+        let fetch = fetch::Policy {
+            max_bytes: options.max_bytes,
+            timeout: options.timeout,
+            ..fetch.clone()
+        };
+        let client = fetch.build(reqwest::Client::builder)?;
+
+        Ok(Self { client, cache, options })
+    }
+
+    /// Extract the main content of the page at `url` as plain text.
+    ///
+    /// Returns `None` when the page has no recognisable article content.
+    #[tracing::instrument(skip(self))]
+    pub async fn extract(&self, url: &str) -> Result<Option<String>, Error> {
+        let cache_key = format!("{}{}", CACHE_KEY_PREFIX, url);
+
+        if let Some(content) = self.cache.get(&cache_key).await? {
+            tracing::debug!("Full text cache hit: {}", url);
+            return Ok(Some(content));
+        }
+
+        let html = self.download(url).await?;
+        let content = match extract_main_content(&html) {
+            Some(content) => truncate(&content, self.options.max_chars),
+            None => {
+                tracing::debug!("No main content found: {}", url);
+                return Ok(None);
+            }
+        };
+
+        self.cache.set_ex(&cache_key, &content, self.options.cache_ttl).await?;
+
+        Ok(Some(content))
+    }
+
+    async fn download(&self, url: &str) -> Result<String, Error> {
+        let url = url.parse().map_err(|_| Error::InvalidUrl(url.to_string()))?;
+        let response = self.client.send(self.client.get(url)).await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(Error::ResponseStatusNotOk(status));
+        }
+
+        let content_type = response.headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        if !content_type.is_empty() && !content_type.contains("html") {
+            return Err(Error::NotHtml(content_type));
+        }
+
+        let body = self.client.read_body(response).await?;
+
+        Ok(String::from_utf8_lossy(&body).into_owned())
+    }
+}
+
+/// Truncate to `max_chars` characters, marking the cut with an ellipsis.
+fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((idx, _)) => format!("{}…", text[..idx].trim_end()),
+        None => text.to_string(),
+    }
+}
+
+const UNLIKELY_TAGS: [&str; 8] = ["nav", "aside", "footer", "header", "form", "script", "style", "noscript"];
+const UNLIKELY_HINTS: [&str; 12] = ["comment", "footer", "sidebar", "nav", "menu", "share", "social", "related", "promo", "advert", "banner", "cookie"];
+const LIKELY_HINTS: [&str; 7] = ["article", "body", "content", "entry", "main", "post", "text"];
+
+/// Minimum length of a paragraph to be taken into account when scoring.
+const MIN_PARAGRAPH_CHARS: usize = 25;
+/// Minimum length of the extracted content to be considered an article.
+const MIN_CONTENT_CHARS: usize = 200;
+
+/// Find the element holding the main content of a page and return its text.
+///
+/// Every paragraph adds a score based on its length and number of commas to its parent,
+/// and half of it to its grandparent. Candidates start from a score depending on their
+/// tag, class and id, and the final score is scaled down by the candidate's link density.
+fn extract_main_content(html: &str) -> Option<String> {
+    let document = Html::parse_document(html);
+    let paragraph_selector = Selector::parse("p, pre").unwrap();
+
+    let mut scores = HashMap::new();
+    for paragraph in document.select(&paragraph_selector) {
+        if is_unlikely(paragraph) {
+            continue;
+        }
+
+        let text = collapse_whitespace(&paragraph.text().collect::<String>());
+        let len = text.chars().count();
+        if len < MIN_PARAGRAPH_CHARS {
+            continue;
+        }
+
+        let score = 1.0 + text.matches(',').count() as f64 + (len / 100).min(3) as f64;
+
+        let Some(parent) = paragraph.parent().and_then(ElementRef::wrap) else { continue };
+        *scores.entry(parent.id()).or_insert_with(|| initial_score(parent)) += score;
+
+        if let Some(grandparent) = parent.parent().and_then(ElementRef::wrap) {
+            *scores.entry(grandparent.id()).or_insert_with(|| initial_score(grandparent)) += score / 2.0;
+        }
+    }
+
+    let best = scores.into_iter()
+        .filter_map(|(id, score)| {
+            let element = ElementRef::wrap(document.tree.get(id)?)?;
+            Some((element, score * (1.0 - link_density(element))))
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(element, _)| element)?;
+
+    let block_selector = Selector::parse("p, pre, h1, h2, h3, h4, h5, h6, li, blockquote").unwrap();
+    let mut seen = HashSet::new();
+    let mut blocks = Vec::new();
+    for block in best.select(&block_selector) {
+        seen.insert(block.id());
+
+        // nested blocks are already part of their ancestor's text
+        let nested = block.ancestors()
+            .take_while(|node| node.id() != best.id())
+            .any(|node| seen.contains(&node.id()));
+        if nested || is_unlikely(block) {
+            continue;
+        }
+
+        let text = collapse_whitespace(&block.text().collect::<String>());
+        if !text.is_empty() {
+            blocks.push(text);
+        }
+    }
+
+    let content = blocks.join("\n\n");
+    if content.chars().count() < MIN_CONTENT_CHARS {
+        return None;
+    }
+
+    Some(content)
+}
+
+fn initial_score(element: ElementRef) -> f64 {
+    let tag_score = match element.value().name() {
+        "article" | "main" => 10.0,
+        "div" => 5.0,
+        "pre" | "td" | "blockquote" => 3.0,
+        "address" | "ol" | "ul" | "dl" | "dd" | "dt" | "li" | "form" => -3.0,
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th" => -5.0,
+        _ => 0.0,
+    };
+
+    tag_score + class_weight(element)
+}
+
+fn class_weight(element: ElementRef) -> f64 {
+    let hints = format!(
+        "{} {}",
+        element.value().attr("class").unwrap_or_default(),
+        element.value().attr("id").unwrap_or_default(),
+    )
+        .to_lowercase();
+
+    let mut weight = 0.0;
+    if UNLIKELY_HINTS.iter().any(|hint| hints.contains(hint)) {
+        weight -= 25.0;
+    }
+    if LIKELY_HINTS.iter().any(|hint| hints.contains(hint)) {
+        weight += 25.0;
+    }
+
+    weight
+}
+
+/// Whether the element or one of its ancestors is page chrome rather than content.
+fn is_unlikely(element: ElementRef) -> bool {
+    std::iter::once(element)
+        .chain(element.ancestors().filter_map(ElementRef::wrap))
+        .any(|el| UNLIKELY_TAGS.contains(&el.value().name()) || class_weight(el) < 0.0)
+}
+
+/// Share of the element's text that is inside links.
+fn link_density(element: ElementRef) -> f64 {
+    let text_len = element.text().map(|s| s.trim().len()).sum::<usize>();
+    if text_len == 0 {
+        return 1.0;
+    }
+
+    let link_selector = Selector::parse("a").unwrap();
+    let link_len = element.select(&link_selector)
+        .flat_map(|link| link.text())
+        .map(|s| s.trim().len())
+        .sum::<usize>();
+
+    link_len as f64 / text_len as f64
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use rssbot_test_support::feed_server::fixture_path;
+
+    use super::*;
+
+    fn extract(name: &str) -> Option<String> {
+        let html = std::fs::read_to_string(fixture_path(&format!("pages/{}", name))).unwrap();
+        extract_main_content(&html)
+    }
+
+    #[test]
+    fn extracts_the_article_without_the_page_chrome() {
+        let content = extract("article.html").unwrap();
+        let blocks = content.split("\n\n").collect::<Vec<_>>();
+
+        assert_eq!(blocks[..3], ["Rewriting the sync loop", "By Alex, June 3", "The old sync loop fetched every feed, one after the other, \
+            and sent new items right away. It worked, but a single slow feed held up everything behind it, and a crash in the middle \
+            of a run meant sending the same items again."]);
+        assert_eq!(blocks[6..], [
+            "Pending rows are sent as soon as they are due.",
+            "Failed rows are retried with a backoff.",
+            // once, although the paragraph is inside the quote
+            "Claim first, send later, and never hold a transaction open across the network.",
+            "next_attempt_at = now + backoff(attempts)",
+        ]);
+        for chrome in ["Archive", "Related posts", "Great post", "Copyright", "analytics"] {
+            assert!(!content.contains(chrome), "{}", chrome);
+        }
+    }
+
+    #[test]
+    fn prefers_text_over_lists_of_links() {
+        let content = extract("link-list.html").unwrap();
+
+        assert!(content.starts_with("This week was mostly about the feed parser."), "{}", content);
+        assert!(!content.contains("anchor text"), "{}", content);
+    }
+
+    #[test]
+    fn pages_without_an_article_have_no_content() {
+        assert_eq!(extract("short.html"), None);
+    }
+}
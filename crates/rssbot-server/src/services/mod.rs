@@ -1,2 +1,7 @@
+pub mod admin;
+pub mod cache;
+pub mod delivery;
+pub mod extractor;
 pub mod subscription;
+pub mod telegraph;
 pub mod user;
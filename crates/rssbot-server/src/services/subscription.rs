@@ -1,38 +1,127 @@
-use std::str::FromStr;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use chrono::NaiveDateTime;
-use sea_orm::ActiveValue;
+use sea_orm::{ActiveValue, Condition, DatabaseTransaction, DbBackend, QueryOrder, QuerySelect, Statement, TransactionTrait};
+use sea_orm::sea_query::OnConflict;
 use sea_orm::prelude::*;
-use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+use teloxide::utils::{html, markdown};
+use tokio_util::sync::CancellationToken;
 
-use rssbot_common::chrono_utils;
-use rssbot_entities::subscription;
+use rssbot_common::observability::metrics::metrics;
+use rssbot_common::config::Config;
+use rssbot_entities::{disabled_feed, subscription, user};
+
+use crate::{credentials, feed, fetch};
+use crate::services::{delivery, extractor, telegraph};
 
 #[derive(Debug, Clone)]
 pub struct Service {
     db: DatabaseConnection,
-    bot: Bot,
+    client: fetch::Client,
+    delivery: Arc<delivery::Service>,
+    extractor: Arc<extractor::Service>,
+    telegraph: Option<Arc<telegraph::Service>>,
+    feed_options: feed::Options,
+    /// Encrypts the credentials of subscriptions, which can't have any without it.
+    cipher: Option<credentials::Cipher>,
+    limits: Limits,
+}
+
+/// Subscription limits, `0` for none.
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    /// Subscriptions of a user, unless the user has their own limit.
+    pub per_user: u64,
+    pub per_chat: u64,
+    /// Subscriptions of all users.
+    pub global: u64,
+    /// Shortest time between two fetches of a feed, unless its owner has their own.
+    pub min_poll_interval: Duration,
+}
+
+impl Limits {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            per_user: config.max_subscriptions_per_user,
+            per_chat: config.max_subscriptions_per_chat,
+            global: config.max_subscriptions,
+            min_poll_interval: Duration::from_secs(config.min_poll_interval_secs),
+        }
+    }
 }
 
+/// How much earlier than its poll interval a feed may be fetched again, as sync runs don't
+/// start exactly on schedule.
+const SCHEDULE_SLACK: Duration = Duration::from_secs(5);
+
+/// Key of the Postgres advisory lock held while a subscription is counted and added.
+const QUOTA_LOCK_KEY: i64 = 0x7273_7362_7175;
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("Subscription already exists")]
     SubscriptionAlreadyExists,
     #[error("Subscription created by other user")]
     SubscriptionCreatedByOtherUser,
+    #[error("Subscription not found")]
+    SubscriptionNotFound,
+    #[error("You have reached the limit of {0} subscriptions, unsubscribe from a feed first")]
+    UserQuotaExceeded(u64),
+    #[error("This chat has reached the limit of {0} subscriptions")]
+    ChatQuotaExceeded(u64),
+    #[error("The bot has reached its limit of subscriptions, try again later")]
+    GlobalQuotaExceeded,
+    #[error("This feed has been disabled by the administrators")]
+    FeedDisabled,
     #[error("Database error: {0}")]
     Database(#[from] DbErr),
     #[error("RSS error: {0}")]
     Rss(#[from] SubscriptionError),
+    #[error("Delivery error: {0}")]
+    Delivery(#[from] delivery::Error),
+    #[error("{0}")]
+    Credentials(#[from] credentials::Error),
 }
 
 impl Service {
-    pub fn new(db: DatabaseConnection, bot: Bot) -> Self {
-        Self { db, bot }
+    pub fn new(
+        db: DatabaseConnection,
+        delivery: Arc<delivery::Service>,
+        extractor: Arc<extractor::Service>,
+        telegraph: Option<Arc<telegraph::Service>>,
+        feed_options: feed::Options,
+        fetch: fetch::Policy,
+        cipher: Option<credentials::Cipher>,
+    ) -> Result<Self, fetch::Error> {
+        Ok(Self {
+            db,
+            client: fetch.build(reqwest::Client::builder)?,
+            delivery,
+            extractor,
+            telegraph,
+            feed_options,
+            cipher,
+            limits: Limits::default(),
+        })
+    }
+
+    /// Enforce `limits`, there are none otherwise.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Check that the fetch policy allows subscribing to `url`.
+    #[tracing::instrument(skip(self))]
+    pub async fn check_url(&self, url: &reqwest::Url) -> Result<(), fetch::Error> {
+        self.client.check(url).await
     }
 
     #[tracing::instrument]
-    pub async fn add_subscription(&self, user_id: i64, target_chat: i64, url: String) -> Result<subscription::Model, Error> {
+    pub async fn add_subscription(&self, user_id: i64, target_chat: i64, url: String, backfill: i32) -> Result<subscription::Model, Error> {
         let existing = subscription::Entity::find()
             .filter(subscription::Column::UserRefer.eq(user_id))
             .filter(subscription::Column::Url.eq(&url))
@@ -48,6 +137,15 @@ impl Service {
             };
         }
 
+        if disabled_feed::Entity::find_by_id(feed_key(&url)).one(&self.db).await?.is_some() {
+            return Err(Error::FeedDisabled);
+        }
+
+        // counted and inserted under one lock, so that concurrent additions can't both fit
+        let txn = self.db.begin().await?;
+        lock_quota(&txn).await?;
+        self.check_quota_with(&txn, user_id, Some(target_chat)).await?;
+
         let subscription = subscription::ActiveModel {
             user_refer: ActiveValue::Set(user_id),
             target_chat: ActiveValue::Set(target_chat),
@@ -55,16 +153,62 @@ impl Service {
             last_updated: ActiveValue::Set(chrono::Utc::now().naive_utc()),
             last_sent: ActiveValue::Set(None),
             last_error: ActiveValue::Set(None),
+            initial_backfill: ActiveValue::Set((backfill > 0).then_some(backfill)),
+            full_text: ActiveValue::Set(false),
+            telegraph: ActiveValue::Set(false),
+            credentials: ActiveValue::Set(None),
+            name_header: ActiveValue::Set(false),
+            last_fetched: ActiveValue::Set(None),
             ..Default::default()
         }
-            .insert(&self.db)
+            .insert(&txn)
             .await?;
+        txn.commit().await?;
 
         tracing::debug!("Subscription added: {:?}", subscription);
 
         Ok(subscription)
     }
 
+    /// Check that `user_id` may add another subscription, to `target_chat` if it's known yet.
+    #[tracing::instrument]
+    pub async fn check_quota(&self, user_id: i64, target_chat: Option<i64>) -> Result<(), Error> {
+        self.check_quota_with(&self.db, user_id, target_chat).await
+    }
+
+    /// [`Service::check_quota`] on `db`, see [`lock_quota`] for checks that must still hold
+    /// when the subscription is added.
+    async fn check_quota_with(&self, db: &impl ConnectionTrait, user_id: i64, target_chat: Option<i64>) -> Result<(), Error> {
+        let user_limit = user::Entity::find_by_id(user_id).one(db).await?
+            .and_then(|user| user.max_subscriptions)
+            .map_or(self.limits.per_user, |limit| limit.max(0) as u64);
+        if user_limit > 0 {
+            let count = subscription::Entity::find()
+                .filter(subscription::Column::UserRefer.eq(user_id))
+                .count(db)
+                .await?;
+            if count >= user_limit {
+                return Err(Error::UserQuotaExceeded(user_limit));
+            }
+        }
+
+        if let (Some(chat_id), limit @ 1..) = (target_chat, self.limits.per_chat) {
+            let count = subscription::Entity::find()
+                .filter(subscription::Column::TargetChat.eq(chat_id))
+                .count(db)
+                .await?;
+            if count >= limit {
+                return Err(Error::ChatQuotaExceeded(limit));
+            }
+        }
+
+        if self.limits.global > 0 && subscription::Entity::find().count(db).await? >= self.limits.global {
+            return Err(Error::GlobalQuotaExceeded);
+        }
+
+        Ok(())
+    }
+
     #[tracing::instrument]
     pub async fn remove_subscription(&self, user_id: i64, id: i32) -> Result<(), Error> {
         subscription::ActiveModel {
@@ -78,6 +222,83 @@ impl Service {
         Ok(())
     }
 
+    #[tracing::instrument]
+    pub async fn set_full_text(&self, user_id: i64, id: i32, enabled: bool) -> Result<subscription::Model, Error> {
+        let subscription = subscription::Entity::find_by_id(id)
+            .filter(subscription::Column::UserRefer.eq(user_id))
+            .one(&self.db)
+            .await?
+            .ok_or(Error::SubscriptionNotFound)?;
+
+        let mut act: subscription::ActiveModel = subscription.into();
+        act.full_text = ActiveValue::Set(enabled);
+
+        Ok(act.update(&self.db).await?)
+    }
+
+    #[tracing::instrument]
+    pub async fn set_telegraph(&self, user_id: i64, id: i32, enabled: bool) -> Result<subscription::Model, Error> {
+        let subscription = subscription::Entity::find_by_id(id)
+            .filter(subscription::Column::UserRefer.eq(user_id))
+            .one(&self.db)
+            .await?
+            .ok_or(Error::SubscriptionNotFound)?;
+
+        let mut act: subscription::ActiveModel = subscription.into();
+        act.telegraph = ActiveValue::Set(enabled);
+
+        Ok(act.update(&self.db).await?)
+    }
+
+    #[tracing::instrument]
+    pub async fn set_name_header(&self, user_id: i64, id: i32, enabled: bool) -> Result<subscription::Model, Error> {
+        let subscription = subscription::Entity::find_by_id(id)
+            .filter(subscription::Column::UserRefer.eq(user_id))
+            .one(&self.db)
+            .await?
+            .ok_or(Error::SubscriptionNotFound)?;
+
+        let mut act: subscription::ActiveModel = subscription.into();
+        act.name_header = ActiveValue::Set(enabled);
+
+        Ok(act.update(&self.db).await?)
+    }
+
+    /// Name the subscription, or go back to the feed title with `None`.
+    #[tracing::instrument]
+    pub async fn set_display_name(&self, user_id: i64, id: i32, name: Option<String>) -> Result<subscription::Model, Error> {
+        let subscription = subscription::Entity::find_by_id(id)
+            .filter(subscription::Column::UserRefer.eq(user_id))
+            .one(&self.db)
+            .await?
+            .ok_or(Error::SubscriptionNotFound)?;
+
+        let mut act: subscription::ActiveModel = subscription.into();
+        act.display_name = ActiveValue::Set(name);
+
+        Ok(act.update(&self.db).await?)
+    }
+
+    /// Replace the credentials sent when fetching the feed, or remove them.
+    #[tracing::instrument]
+    pub async fn set_credentials(&self, user_id: i64, id: i32, credentials: Option<credentials::Credentials>) -> Result<subscription::Model, Error> {
+        let sealed = match &credentials {
+            Some(credentials) => Some(self.cipher.as_ref().ok_or(credentials::Error::NoKey)?.seal(credentials)?),
+            None => None,
+        };
+
+        let subscription = subscription::Entity::find_by_id(id)
+            .filter(subscription::Column::UserRefer.eq(user_id))
+            .one(&self.db)
+            .await?
+            .ok_or(Error::SubscriptionNotFound)?;
+
+        let mut act: subscription::ActiveModel = subscription.into();
+        act.credentials = ActiveValue::Set(sealed);
+
+        Ok(act.update(&self.db).await?)
+    }
+
     #[tracing::instrument]
     pub async fn list_subscriptions(&self, user_id: i64) -> Result<Vec<subscription::Model>, Error> {
         let subscriptions = subscription::Entity::find()
@@ -88,6 +309,79 @@ impl Service {
         Ok(subscriptions)
     }
 
+    #[tracing::instrument]
+    pub async fn get_subscription(&self, id: i32) -> Result<subscription::Model, Error> {
+        subscription::Entity::find_by_id(id)
+            .one(&self.db)
+            .await?
+            .ok_or(Error::SubscriptionNotFound)
+    }
+
+    /// Subscriptions of every user whose URL or name contains `query`, or whose owner or chat
+    /// is `query`, newest first.
+    #[tracing::instrument]
+    pub async fn search_subscriptions(&self, query: &str, limit: u64) -> Result<Vec<subscription::Model>, Error> {
+        let mut condition = Condition::any()
+            .add(subscription::Column::Url.contains(query))
+            .add(subscription::Column::FeedTitle.contains(query))
+            .add(subscription::Column::DisplayName.contains(query));
+        if let Ok(id) = query.parse::<i64>() {
+            condition = condition
+                .add(subscription::Column::UserRefer.eq(id))
+                .add(subscription::Column::TargetChat.eq(id));
+        }
+
+        let subscriptions = subscription::Entity::find()
+            .filter(condition)
+            .order_by_desc(subscription::Column::Id)
+            .limit(limit)
+            .all(&self.db)
+            .await?;
+
+        Ok(subscriptions)
+    }
+
+    /// Stop or resume syncing every subscription to `url`, returning how many there are.
+    /// A disabled feed can't be subscribed to either, even without subscriptions to it.
+    #[tracing::instrument]
+    pub async fn set_feed_disabled(&self, url: &str, disabled: bool) -> Result<u64, Error> {
+        let key = feed_key(url);
+        if disabled {
+            disabled_feed::Entity::insert(disabled_feed::ActiveModel {
+                url: ActiveValue::Set(key.clone()),
+                disabled_at: ActiveValue::Set(chrono::Utc::now().naive_utc()),
+            })
+                .on_conflict(OnConflict::column(disabled_feed::Column::Url).do_nothing().to_owned())
+                .do_nothing()
+                .exec(&self.db)
+                .await?;
+        } else {
+            disabled_feed::Entity::delete_by_id(key.clone()).exec(&self.db).await?;
+        }
+
+        let urls: Vec<String> = subscription::Entity::find()
+            .select_only()
+            .column(subscription::Column::Url)
+            .into_tuple()
+            .all(&self.db)
+            .await?;
+
+        Ok(urls.iter().filter(|url| feed_key(url) == key).count() as u64)
+    }
+
+    /// Keys of the disabled feeds, see [`feed_key`].
+    #[tracing::instrument]
+    pub async fn disabled_feeds(&self) -> Result<HashSet<String>, Error> {
+        let keys: Vec<String> = disabled_feed::Entity::find()
+            .select_only()
+            .column(disabled_feed::Column::Url)
+            .into_tuple()
+            .all(&self.db)
+            .await?;
+
+        Ok(keys.into_iter().collect())
+    }
+
     #[tracing::instrument]
     pub async fn list_subscriptions_for_chat(&self, chat_id: i64) -> Result<Vec<subscription::Model>, Error> {
         let subscriptions = subscription::Entity::find()
@@ -98,43 +392,123 @@ impl Service {
         Ok(subscriptions)
     }
 
-    #[tracing::instrument]
-    pub async fn sync_subscriptions(&self) -> Result<(), Error> {
-        log::info!("Syncing subscriptions");
+    /// Sync all subscriptions, each run is the root of its own trace.
+    #[tracing::instrument(name = "sync_run", parent = None, skip_all, fields(subscriptions = tracing::field::Empty))]
+    pub async fn sync_subscriptions(&self, shutdown: &CancellationToken) -> Result<(), Error> {
+        tracing::info!("Syncing subscriptions");
+        let _timer = metrics().sync_duration.start_timer();
 
-        let subscriptions = subscription::Entity::find().all(&self.db).await?;
+        let started = chrono::Utc::now().naive_utc();
+        let disabled = self.disabled_feeds().await?;
+        let subscriptions = subscription::Entity::find()
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .filter(|subscription| !disabled.contains(&feed_key(&subscription.url)))
+            .collect::<Vec<_>>();
+        tracing::Span::current().record("subscriptions", subscriptions.len());
+
+        // only owners with something to look out for
+        let owners = user::Entity::find()
+            .filter(Condition::any()
+                .add(user::Column::MinPollIntervalSecs.is_not_null())
+                .add(user::Column::Banned.eq(true)))
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(|user| (user.telegram_user_id, user))
+            .collect::<HashMap<_, _>>();
 
         for subscription in subscriptions {
-            match self.sync_single_subscription(&subscription).await {
-                Ok((pub_date, len)) => {
-                    log::info!("Subscription {} synced, {} updates.", subscription.id, len);
+            // finish the subscription in progress, the rest is picked up by the next run
+            if shutdown.is_cancelled() {
+                tracing::info!("Shutting down, stopped syncing before subscription {}", subscription.id);
+                break;
+            }
+
+            let owner = owners.get(&subscription.user_refer);
+            if owner.is_some_and(|owner| owner.banned) {
+                tracing::debug!("Owner of subscription {} is banned, skipping", subscription.id);
+                continue;
+            }
+
+            let poll_interval = owner.and_then(|owner| owner.min_poll_interval_secs)
+                .map_or(self.limits.min_poll_interval, |secs| Duration::from_secs(secs.max(0) as u64));
+            let since_fetched = subscription.last_fetched
+                .and_then(|fetched| (started - fetched).to_std().ok());
+            if since_fetched.is_some_and(|elapsed| elapsed + SCHEDULE_SLACK < poll_interval) {
+                tracing::debug!("Subscription {} was fetched less than {:?} ago, skipping", subscription.id, poll_interval);
+                continue;
+            }
+
+            self.sync_and_record(subscription, started).await?;
+        }
 
-                    let mut act: subscription::ActiveModel = subscription.into();
-                    act.last_updated = ActiveValue::Set(chrono::Utc::now().naive_utc());
-                    act.last_sent = ActiveValue::Set(pub_date);
-                    act.last_error = ActiveValue::Set(None);
+        Ok(())
+    }
 
-                    act.update(&self.db).await?;
+    /// Sync a subscription now, whatever its poll interval.
+    #[tracing::instrument]
+    pub async fn force_sync(&self, id: i32) -> Result<subscription::Model, Error> {
+        let subscription = self.get_subscription(id).await?;
+        self.sync_and_record(subscription, chrono::Utc::now().naive_utc()).await
+    }
+
+    /// Sync `subscription` and record how it went, returning it updated.
+    async fn sync_and_record(&self, subscription: subscription::Model, started: NaiveDateTime) -> Result<subscription::Model, Error> {
+        match self.sync_single_subscription(&subscription).await {
+            Ok((metadata, progress)) => {
+                let mut act: subscription::ActiveModel = subscription.clone().into();
+                act.last_fetched = ActiveValue::Set(Some(started));
+                act.feed_title = ActiveValue::Set(metadata.title);
+                act.feed_link = ActiveValue::Set(metadata.link.map(String::from));
+                act.feed_description = ActiveValue::Set(metadata.description);
+                act.feed_icon = ActiveValue::Set(metadata.icon.map(String::from));
+
+                if let Some(cursor) = progress.cursor {
+                    act.last_sent = ActiveValue::Set(Some(cursor));
                 }
-                Err(err) => {
-                    log::error!("Failed to sync subscription: {}", err);
 
-                    let mut act: subscription::ActiveModel = subscription.into();
-                    act.last_updated = ActiveValue::Set(chrono::Utc::now().naive_utc());
-                    act.last_sent = ActiveValue::Set(None);
-                    act.last_error = ActiveValue::Set(Some(err.to_string()));
-                    act.update(&self.db).await?;
+                // the backfill is done once anything went through, the rest follows the cursor
+                if progress.delivered > 0 || progress.error.is_none() {
+                    act.initial_backfill = ActiveValue::Set(None);
+                }
+
+                match progress.error {
+                    None => {
+                        tracing::info!("Subscription {} synced, {} updates.", subscription.id, progress.delivered);
 
-                    continue;
+                        // advance to the newest delivered item, items dated between it and now must not be skipped
+                        act.last_updated = ActiveValue::Set(progress.cursor.unwrap_or(subscription.last_updated));
+                        act.last_error = ActiveValue::Set(None);
+                    }
+                    Some(err) => {
+                        tracing::error!("Subscription {} partially synced, {} updates delivered before failure: {}", subscription.id, progress.delivered, err);
+
+                        // resume from the first undelivered item on the next run
+                        act.last_updated = ActiveValue::Set(progress.cursor.unwrap_or(subscription.last_updated));
+                        act.last_error = ActiveValue::Set(Some(err.to_string()));
+                    }
                 }
-            };
-        }
 
-        Ok(())
+                Ok(act.update(&self.db).await?)
+            }
+            Err(err) => {
+                tracing::error!("Failed to sync subscription: {}", err);
+
+                // nothing was delivered, keep the cursor where it is
+                let mut act: subscription::ActiveModel = subscription.into();
+                act.last_fetched = ActiveValue::Set(Some(started));
+                act.last_error = ActiveValue::Set(Some(err.to_string()));
+                Ok(act.update(&self.db).await?)
+            }
+        }
     }
 
-    async fn sync_single_subscription(&self, subscription: &subscription::Model) -> Result<(Option<NaiveDateTime>, usize), Error> {
-        let feed = match Self::get_feed(subscription).await {
+    #[tracing::instrument(skip_all, fields(subscription.id = subscription.id, telegram.chat_id = subscription.target_chat))]
+    async fn sync_single_subscription(&self, subscription: &subscription::Model) -> Result<(feed::Metadata, Progress<Error>), Error> {
+        let fetched_at = chrono::Utc::now().naive_utc();
+        let feed = match self.get_feed(subscription).await {
             Ok(feed) => feed,
             Err(err) => {
                 tracing::error!("Failed to fetch feed: {:?}", err);
@@ -144,116 +518,391 @@ impl Service {
 
         tracing::debug!("Fetched feed: {:?}", feed);
 
-        let new_items = feed.items()
-            .iter()
-            .filter(|item| {
-                let item_date = match item.pub_date().and_then(chrono_utils::parse_datetime) {
-                    Some(date) => date,
-                    None => {
-                        tracing::warn!("Date format is not recognized: {:?}", item.pub_date());
-                        return false;
-                    }
-                };
-
-                item_date > subscription.last_updated
+        // items without a date of their own can't be told apart from the ones already sent, so
+        // they are never new and only count towards the initial backfill
+        let backfilling = subscription.initial_backfill.is_some();
+
+        let mut items = feed::items(&feed, fetched_at, &self.feed_options)
+            .into_iter()
+            .filter_map(|item| match item {
+                Ok(item) if item.dated_by == feed::DateSource::Item => Some((item.published, item)),
+                Ok(item) if backfilling => Some((item.published.min(subscription.last_updated), item)),
+                Ok(item) => {
+                    tracing::debug!("Skipping undated item: {}", item.title);
+                    None
+                }
+                Err(skipped) => {
+                    tracing::warn!("Skipping item: {}", skipped);
+                    None
+                }
             })
             .collect::<Vec<_>>();
 
-        log::info!("Subscription {} has {} updates, fetched on {}", subscription.id, new_items.len(), feed.pub_date().unwrap_or_default());
-
-        if new_items.is_empty() {
-            return Ok((None, 0));
+        // deliver oldest first, so the cursor can stop at the first failure; feeds list the newest
+        // first, keep that order for items with the same date
+        items.reverse();
+        items.sort_by_key(|(date, _)| *date);
+
+        // items dated in the future wait for their date, so that the cursor never passes the
+        // fetch time and skips items published before that date
+        let due = items.partition_point(|(date, _)| *date <= fetched_at);
+        if due < items.len() {
+            tracing::debug!("Holding back {} items dated after {}", items.len() - due, fetched_at);
+            items.truncate(due);
         }
 
-        let len = new_items.len();
-        for item in new_items {
-            if let Err(err) = self.handle_new_item(subscription, item).await {
-                log::error!("Failed to handle new item: {}", err);
-            }
-        }
+        // new items are the ones after the cursor, extended to the latest N on the first sync
+        let first_new = items.partition_point(|(date, _)| *date <= subscription.last_updated);
+        let backfill = subscription.initial_backfill.unwrap_or_default().max(0) as usize;
+        let new_items = items.split_off(first_new.min(items.len().saturating_sub(backfill)));
+
+        tracing::info!("Subscription {} has {} updates, fetched on {}", subscription.id, new_items.len(), feed.channel.pub_date().unwrap_or_default());
+
+        // named after this fetch, the stored title may be outdated or missing
+        let metadata = feed::metadata(&feed);
+        let header = subscription.name_header.then(|| {
+            subscription.display_name.clone()
+                .or_else(|| metadata.title.clone())
+                .unwrap_or_else(|| subscription.url.clone())
+        });
+
+        let progress = deliver_in_order(new_items, |item| self.handle_new_item(subscription, header.as_deref(), item)).await;
+
+        Ok((metadata, progress))
+    }
+
+    async fn handle_new_item(&self, subscription: &subscription::Model, header: Option<&str>, item: feed::Item) -> Result<(), Error> {
+        let feed::Item { title, description, content, link, .. } = item;
 
-        Ok((
-            feed
-                .items()
-                .iter()
-                .filter_map(|item| item.pub_date())
-                .filter_map(|date| NaiveDateTime::parse_from_str(date, "%a, %d %b %Y %H:%M:%S %z").ok())
-                .max(),
-            len
-        ))
-    }
-
-    async fn handle_new_item(&self, subscription: &subscription::Model, item: &rss::Item) -> Result<(), Error> {
-        let (title, description, link) = match (item.title(), item.description(), item.link()) {
-            (Some(title), Some(description), Some(link)) => (title, description, link),
-            _ => {
-                tracing::warn!("Item is missing title, description, or link: {:?}", item);
-                return Ok(());
+        let full_text = if subscription.full_text {
+            match self.extractor.extract(link.as_str()).await {
+                Ok(content) => content,
+                Err(err) => {
+                    tracing::warn!("Failed to extract full text, falling back to description: {}", err);
+                    None
+                }
             }
+        } else {
+            None
         };
 
-        let link = match link.parse() {
-            Ok(link) => link,
-            Err(err) => {
-                tracing::warn!("Failed to parse link: {}", err);
-                return Ok(());
+        let page_url = match (&self.telegraph, subscription.telegraph) {
+            (Some(telegraph), true) => {
+                let content = match &full_text {
+                    Some(text) => text.split("\n\n")
+                        .map(|paragraph| format!("<p>{}</p>", html::escape(paragraph)))
+                        .collect::<String>(),
+                    None => content.unwrap_or_else(|| description.clone()),
+                };
+
+                if !telegraph.is_long(&content) {
+                    tracing::debug!("Item is too short for a Telegraph page, sending it inline");
+                    None
+                } else {
+                    match telegraph.publish(&title, &content, Some(link.as_str())).await {
+                        Ok(url) => Some(url),
+                        Err(err) => {
+                            tracing::warn!("Failed to publish Telegraph page, sending the item inline: {}", err);
+                            None
+                        }
+                    }
+                }
             }
+            _ => None,
         };
 
-        let message = format!(
-            "📰 *{}*\n\n{}",
-            title,
-            description,
-        );
-
-        // notify the user
-        match self.bot.send_message(ChatId(subscription.target_chat), message)
-            .reply_markup(teloxide::types::ReplyMarkup::InlineKeyboard(teloxide::types::InlineKeyboardMarkup::new(vec![
-                vec![
-                    teloxide::types::InlineKeyboardButton::url("Read more", link),
-                ],
-            ])))
-            .parse_mode(teloxide::types::ParseMode::MarkdownV2)
-            .send()
-            .await {
-            Ok(_) => {
-                tracing::debug!("Sent message for item: {}", item.title().unwrap_or_default());
-            }
-            Err(err) => {
-                log::error!("Failed to send message for item: {}", err);
-            }
+        let mut message = match page_url {
+            Some(page_url) => format!(
+                "📰 *{}*\n\n[Instant View]({})",
+                markdown::escape(&title),
+                markdown::escape_link_url(&page_url),
+            ),
+            None => format!(
+                "📰 *{}*\n\n{}",
+                markdown::escape(&title),
+                markdown::escape(full_text.as_deref().unwrap_or(&description)),
+            ),
+        };
+        if let Some(header) = header {
+            message = format!("{}\n{}", markdown::italic(&markdown::escape(header)), message);
         }
 
+        // queue the notification, the delivery dispatcher sends it
+        self.delivery.enqueue(delivery::NewDelivery {
+            subscription_id: Some(subscription.id),
+            target_chat: subscription.target_chat,
+            text: message,
+            parse_mode: Some(ParseMode::MarkdownV2),
+            button: Some(("Read more".to_string(), link.to_string())),
+        }).await?;
+
+        tracing::debug!("Queued message for item: {}", title);
+
         Ok(())
     }
 
-    #[tracing::instrument]
-    async fn get_feed(subscription: &subscription::Model) -> Result<rss::Channel, SubscriptionError> {
-        let response = match reqwest::get(&subscription.url).await {
+    #[tracing::instrument(
+        name = "feed_fetch",
+        skip_all,
+        fields(
+            otel.kind = "client",
+            otel.status_code = tracing::field::Empty,
+            http.request.method = "GET",
+            url.full = %subscription.url,
+            server.address = tracing::field::Empty,
+            http.response.status_code = tracing::field::Empty,
+        ),
+    )]
+    async fn get_feed(&self, subscription: &subscription::Model) -> Result<feed::Feed, SubscriptionError> {
+        let span = tracing::Span::current();
+        let url = subscription.url.parse::<reqwest::Url>()?;
+        if let Some(host) = url.host_str() {
+            span.record("server.address", host);
+        }
+
+        let request = match &subscription.credentials {
+            Some(sealed) => {
+                let credentials = self.cipher.as_ref().ok_or(credentials::Error::NoKey)?.open(sealed)?;
+                credentials.apply(self.client.get_same_origin(url.clone()))
+            }
+            None => self.client.get(url.clone()),
+        };
+
+        let started = Instant::now();
+        let response = match self.client.send(request).await {
             Ok(response) => response,
             Err(err) => {
+                metrics().feed_fetch_duration.with_label_values(&["error"]).observe(started.elapsed().as_secs_f64());
+                span.record("otel.status_code", "ERROR");
                 return Err(err.into());
             }
         };
 
         let status = response.status();
+        span.record("http.response.status_code", status.as_u16());
+        if !status.is_success() {
+            span.record("otel.status_code", "ERROR");
+        }
+        // relative links resolve against where the feed ended up after redirects
+        let url = response.url().clone();
+        let content_type = response.headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let body = self.client.read_body(response).await;
+        metrics().feed_fetch_duration.with_label_values(&[status.as_str()]).observe(started.elapsed().as_secs_f64());
+
         if !status.is_success() {
             return Err(SubscriptionError::ResponseStatusNotOk(status));
         }
 
-        let body = response.text().await?;
-        let feed = rss::Channel::from_str(&body)?;
+        let feed = match feed::parse(&body?, content_type.as_deref(), &url) {
+            Ok(feed) => feed,
+            Err(err) => {
+                metrics().feed_parse_errors.with_label_values(&["rss"]).inc();
+                span.record("otel.status_code", "ERROR");
+                return Err(err.into());
+            }
+        };
 
         Ok(feed)
     }
 }
 
+/// Make additions of subscriptions in other transactions wait for `txn` to end.
+///
+/// The chat and global limits count subscriptions of every user, so no row of the user
+/// serialises the additions that count towards them. Postgres takes an advisory lock for the
+/// transaction; SQLite runs one write transaction at a time and fails the later of two that
+/// counted concurrently.
+async fn lock_quota(txn: &DatabaseTransaction) -> Result<(), DbErr> {
+    if txn.get_database_backend() == DbBackend::Postgres {
+        txn.execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            "SELECT pg_advisory_xact_lock($1)",
+            [QUOTA_LOCK_KEY.into()],
+        )).await?;
+    }
+
+    Ok(())
+}
+
+/// Key of the feed at `url` among the disabled feeds.
+///
+/// The scheme, fragment, trailing slashes, tracking parameters and the order of the query
+/// parameters are left out, so that variants of the URL can't be used to subscribe to a
+/// disabled feed anyway. The rest of the query stays, it often names the feed.
+pub fn feed_key(url: &str) -> String {
+    let Ok(url) = reqwest::Url::parse(url.trim()) else {
+        return url.trim().to_lowercase();
+    };
+
+    let host = url.host_str().unwrap_or_default();
+    let path = url.path().trim_end_matches('/');
+    let mut key = match url.port() {
+        Some(port) => format!("{}:{}{}", host, port, path),
+        None => format!("{}{}", host, path),
+    };
+
+    let mut query = url.query_pairs()
+        .filter(|(name, _)| !feed::is_tracking_parameter(name))
+        .collect::<Vec<_>>();
+    if !query.is_empty() {
+        query.sort();
+        key.push('?');
+        key.push_str(&url::form_urlencoded::Serializer::new(String::new()).extend_pairs(query).finish());
+    }
+
+    key
+}
+
+/// Result of handing a batch of items over for delivery.
+#[derive(Debug)]
+struct Progress<E> {
+    /// Latest publication date up to which every item was handed over, if any.
+    cursor: Option<NaiveDateTime>,
+    /// Number of items handed over.
+    delivered: usize,
+    /// The failure that stopped the batch, if any.
+    error: Option<E>,
+}
+
+/// Hand items over one by one, stopping at the first failure.
+///
+/// Items must be sorted by publication date; the returned cursor is the date of the last
+/// item that was handed over, so everything after it is retried on the next run. The cursor
+/// stays before a date until every item with that date went through, a failure part way
+/// through them resends the ones already handed over rather than skipping the rest.
+async fn deliver_in_order<T, E, F, Fut>(items: Vec<(NaiveDateTime, T)>, mut deliver: F) -> Progress<E>
+where
+    F: FnMut(T) -> Fut,
+    Fut: std::future::Future<Output = Result<(), E>>,
+{
+    let mut progress = Progress { cursor: None, delivered: 0, error: None };
+
+    let mut items = items.into_iter().peekable();
+    while let Some((date, item)) = items.next() {
+        if let Err(err) = deliver(item).await {
+            progress.error = Some(err);
+            break;
+        }
+
+        progress.delivered += 1;
+        if items.peek().is_none_or(|(next, _)| *next != date) {
+            progress.cursor = Some(date);
+        }
+    }
+
+    progress
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum SubscriptionError {
+    #[error("Invalid feed URL: {0}")]
+    InvalidUrl(#[from] url::ParseError),
+    #[error("Invalid credentials: {0}")]
+    Credentials(#[from] credentials::Error),
     #[error("Failed to fetch feed: {0}")]
-    FetchError(#[from] reqwest::Error),
+    FetchError(#[from] fetch::Error),
     #[error("Failed to parse feed: {0}")]
     ParseError(#[from] rss::Error),
     #[error("Response status is not OK: {0}")]
     ResponseStatusNotOk(reqwest::StatusCode),
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn date(day: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 6, day).unwrap().and_hms_opt(12, 0, 0).unwrap()
+    }
+
+    #[tokio::test]
+    async fn all_items_delivered() {
+        let items = vec![(date(1), 1), (date(2), 2), (date(3), 3)];
+        let progress = deliver_in_order(items, |_| async { Ok::<_, ()>(()) }).await;
+
+        assert_eq!(progress.cursor, Some(date(3)));
+        assert_eq!(progress.delivered, 3);
+        assert!(progress.error.is_none());
+    }
+
+    #[tokio::test]
+    async fn stops_at_first_failure() {
+        let items = vec![(date(1), 1), (date(2), 2), (date(3), 3), (date(4), 4)];
+        let mut attempted = Vec::new();
+        let progress = deliver_in_order(items, |n| {
+            attempted.push(n);
+            async move { if n == 3 { Err("send failed") } else { Ok(()) } }
+        }).await;
+
+        assert_eq!(attempted, vec![1, 2, 3]);
+        assert_eq!(progress.cursor, Some(date(2)));
+        assert_eq!(progress.delivered, 2);
+        assert_eq!(progress.error, Some("send failed"));
+    }
+
+    #[tokio::test]
+    async fn cursor_stays_before_items_with_the_same_date_until_all_went_through() {
+        let items = vec![(date(1), 1), (date(2), 2), (date(2), 3), (date(2), 4)];
+        let progress = deliver_in_order(items, |n| async move { if n == 3 { Err("send failed") } else { Ok(()) } }).await;
+
+        assert_eq!(progress.cursor, Some(date(1)));
+        assert_eq!(progress.delivered, 2);
+        assert_eq!(progress.error, Some("send failed"));
+
+        let items = vec![(date(2), 2), (date(2), 3)];
+        let progress = deliver_in_order(items, |_| async { Ok::<_, ()>(()) }).await;
+
+        assert_eq!(progress.cursor, Some(date(2)));
+    }
+
+    #[tokio::test]
+    async fn first_item_fails() {
+        let items = vec![(date(1), 1), (date(2), 2)];
+        let progress = deliver_in_order(items, |n| async move { if n == 1 { Err("send failed") } else { Ok(()) } }).await;
+
+        assert_eq!(progress.cursor, None);
+        assert_eq!(progress.delivered, 0);
+        assert_eq!(progress.error, Some("send failed"));
+    }
+
+    #[tokio::test]
+    async fn no_items() {
+        let progress = deliver_in_order(Vec::<(NaiveDateTime, ())>::new(), |_| async { Ok::<_, ()>(()) }).await;
+
+        assert_eq!(progress.cursor, None);
+        assert_eq!(progress.delivered, 0);
+        assert!(progress.error.is_none());
+    }
+
+    #[test]
+    fn feed_keys_ignore_variants_of_the_url() {
+        let key = feed_key("https://example.com/feed");
+        assert_eq!(key, "example.com/feed");
+        for url in [
+            "http://example.com/feed", "https://EXAMPLE.com/feed/", "https://example.com:443/feed?utm_source=x",
+            "https://example.com/feed#top", " https://example.com/feed// ",
+        ] {
+            assert_eq!(feed_key(url), key, "{}", url);
+        }
+
+        assert_ne!(feed_key("https://example.com/feeds"), key);
+        assert_ne!(feed_key("https://example.com:8443/feed"), key);
+    }
+
+    #[test]
+    fn feed_keys_keep_the_query_naming_the_feed() {
+        let key = feed_key("https://www.youtube.com/feeds/videos.xml?channel_id=A");
+        assert_eq!(key, "www.youtube.com/feeds/videos.xml?channel_id=A");
+        assert_eq!(feed_key("https://www.youtube.com/feeds/videos.xml?utm_medium=rss&channel_id=A"), key);
+        assert_ne!(feed_key("https://www.youtube.com/feeds/videos.xml?channel_id=B"), key);
+        assert_ne!(feed_key("https://www.youtube.com/feeds/videos.xml"), key);
+
+        assert_eq!(feed_key("https://example.com/index.php?lang=en&feed=rss2"), feed_key("https://example.com/index.php?feed=rss2&lang=en"));
+        assert_ne!(feed_key("https://example.com/index.php?feed=rss2"), feed_key("https://example.com/index.php?feed=atom"));
+    }
+}
@@ -0,0 +1,372 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use ego_tree::NodeRef;
+use scraper::{Html, Node as HtmlNode};
+use serde::{Deserialize, Serialize};
+
+use rssbot_common::config::{Config, TelegraphClient};
+
+use crate::http::TracePropagation;
+
+/// Publishes long items as telegra.ph pages, which Telegram opens with Instant View.
+#[derive(Debug)]
+pub struct Service {
+    client: Box<dyn Client>,
+    author_name: Option<String>,
+    /// Less text than this reads fine inline.
+    min_chars: usize,
+}
+
+/// A node of the Telegraph content format.
+///
+/// See <https://telegra.ph/api#Node>.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum Node {
+    Text(String),
+    Element(NodeElement),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NodeElement {
+    pub tag: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attrs: Option<NodeAttrs>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NodeAttrs {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub href: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub src: Option<String>,
+}
+
+/// A page to be created on Telegraph.
+#[derive(Debug, Clone, Serialize)]
+pub struct NewPage {
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author_url: Option<String>,
+    pub content: Vec<Node>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Telegraph request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("Telegraph API error: {0}")]
+    Api(String),
+    #[error("Page has no content")]
+    EmptyContent,
+}
+
+/// Backend that creates Telegraph pages.
+#[async_trait::async_trait]
+pub trait Client: std::fmt::Debug + Send + Sync {
+    /// Create a page and return its URL.
+    async fn create_page(&self, page: &NewPage) -> Result<String, Error>;
+}
+
+/// Client for the Telegraph HTTP API.
+#[derive(Debug)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    api_url: String,
+    access_token: String,
+    trace_propagation: TracePropagation,
+}
+
+impl HttpClient {
+    pub fn new(api_url: String, access_token: String, trace_propagation: TracePropagation) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            api_url,
+            access_token,
+            trace_propagation,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct CreatePageRequest<'a> {
+    access_token: &'a str,
+    title: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author_name: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author_url: Option<&'a str>,
+    content: &'a [Node],
+    return_content: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiResponse<T> {
+    ok: bool,
+    result: Option<T>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Page {
+    url: String,
+}
+
+#[async_trait::async_trait]
+impl Client for HttpClient {
+    #[tracing::instrument(skip(self, page), fields(title = %page.title))]
+    async fn create_page(&self, page: &NewPage) -> Result<String, Error> {
+        let mut request = self.client
+            .post(format!("{}/createPage", self.api_url.trim_end_matches('/')))
+            .json(&CreatePageRequest {
+                access_token: &self.access_token,
+                title: &page.title,
+                author_name: page.author_name.as_deref(),
+                author_url: page.author_url.as_deref(),
+                content: &page.content,
+                return_content: false,
+            })
+            .build()?;
+        self.trace_propagation.apply(&mut request);
+
+        let response: ApiResponse<Page> = self.client
+            .execute(request)
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        match response {
+            ApiResponse { ok: true, result: Some(page), .. } => Ok(page.url),
+            ApiResponse { error, .. } => Err(Error::Api(error.unwrap_or_else(|| "unknown error".to_string()))),
+        }
+    }
+}
+
+/// Client that only logs pages, for local development without a Telegraph account.
+#[derive(Debug, Default)]
+pub struct StubClient {
+    counter: AtomicU64,
+}
+
+#[async_trait::async_trait]
+impl Client for StubClient {
+    async fn create_page(&self, page: &NewPage) -> Result<String, Error> {
+        let id = self.counter.fetch_add(1, Ordering::Relaxed);
+        let url = format!("https://telegra.ph/stub-{}", id);
+
+        tracing::info!("Telegraph stub created page {}: {} ({} nodes)", url, page.title, page.content.len());
+
+        Ok(url)
+    }
+}
+
+/// Maximum length of a page title accepted by Telegraph.
+const MAX_TITLE_CHARS: usize = 256;
+/// Telegraph rejects pages whose content is larger than 64 KB.
+const MAX_CONTENT_BYTES: usize = 60 * 1024;
+
+impl Service {
+    pub fn new(client: Box<dyn Client>, author_name: Option<String>, min_chars: usize) -> Self {
+        Self { client, author_name, min_chars }
+    }
+
+    /// Build the service from the configuration, `None` when Telegraph publishing is disabled.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let client: Box<dyn Client> = match config.telegraph_client {
+            TelegraphClient::Http => Box::new(HttpClient::new(
+                config.telegraph_api_url.clone(),
+                config.telegraph_access_token.clone()?,
+                TracePropagation::from_config(config),
+            )),
+            TelegraphClient::Stub => Box::<StubClient>::default(),
+            TelegraphClient::Disabled => return None,
+        };
+
+        Some(Self::new(client, config.telegraph_author_name.clone(), config.telegraph_min_chars))
+    }
+
+    /// Whether `html` has enough text to be worth a page.
+    pub fn is_long(&self, html: &str) -> bool {
+        let chars = Html::parse_fragment(html).root_element()
+            .text()
+            .map(|text| text.chars().filter(|c| !c.is_whitespace()).count())
+            .sum::<usize>();
+        chars >= self.min_chars
+    }
+
+    /// Publish an item and return the URL of the page.
+    #[tracing::instrument(skip(self, html))]
+    pub async fn publish(&self, title: &str, html: &str, source_url: Option<&str>) -> Result<String, Error> {
+        let mut content = html_to_nodes(html);
+        if content.is_empty() {
+            return Err(Error::EmptyContent);
+        }
+
+        // drop trailing nodes until the page fits
+        while content.len() > 1 && serde_json::to_vec(&content).map_or(0, |v| v.len()) > MAX_CONTENT_BYTES {
+            content.pop();
+        }
+
+        let page = NewPage {
+            title: title.chars().take(MAX_TITLE_CHARS).collect(),
+            author_name: self.author_name.clone(),
+            author_url: source_url.map(str::to_string),
+            content,
+        };
+
+        self.client.create_page(&page).await
+    }
+}
+
+/// Tags Telegraph accepts as-is.
+const ALLOWED_TAGS: [&str; 24] = [
+    "a", "aside", "b", "blockquote", "br", "code", "em", "figcaption", "figure", "h3", "h4", "hr",
+    "i", "iframe", "img", "li", "ol", "p", "pre", "s", "strong", "u", "ul", "video",
+];
+/// Tags whose content is dropped entirely.
+const DROPPED_TAGS: [&str; 6] = ["script", "style", "noscript", "head", "form", "button"];
+
+/// Convert an HTML fragment into Telegraph nodes.
+///
+/// Unsupported tags are replaced by their children, headings are mapped onto the two
+/// heading levels Telegraph knows, and only `href` and `src` attributes are kept.
+pub fn html_to_nodes(html: &str) -> Vec<Node> {
+    let fragment = Html::parse_fragment(html);
+    let mut nodes = Vec::new();
+    for child in fragment.root_element().children() {
+        convert(child, &mut nodes);
+    }
+
+    trim_blank(nodes)
+}
+
+fn convert(node: NodeRef<HtmlNode>, out: &mut Vec<Node>) {
+    match node.value() {
+        HtmlNode::Text(text) => {
+            let text = text.to_string();
+            if !text.is_empty() {
+                out.push(Node::Text(text));
+            }
+        }
+        HtmlNode::Element(element) => {
+            let name = element.name();
+            if DROPPED_TAGS.contains(&name) {
+                return;
+            }
+
+            let mut children = Vec::new();
+            for child in node.children() {
+                convert(child, &mut children);
+            }
+
+            let tag = match name {
+                "h1" | "h2" | "h3" => "h3",
+                "h4" | "h5" | "h6" => "h4",
+                "strike" | "del" => "s",
+                "ins" => "u",
+                name if ALLOWED_TAGS.contains(&name) => name,
+                // block containers become paragraphs only when they hold bare text
+                "div" | "section" | "article" | "main" | "header" | "footer"
+                if children.iter().all(|child| matches!(child, Node::Text(_))) && !children.is_empty() => "p",
+                _ => {
+                    out.extend(children);
+                    return;
+                }
+            };
+
+            let attrs = match tag {
+                "a" => element.attr("href").map(|href| NodeAttrs { href: Some(href.to_string()), src: None }),
+                "img" | "iframe" | "video" => element.attr("src").map(|src| NodeAttrs { href: None, src: Some(src.to_string()) }),
+                _ => None,
+            };
+
+            // media without a source is useless
+            if matches!(tag, "img" | "iframe" | "video") && attrs.is_none() {
+                return;
+            }
+
+            out.push(Node::Element(NodeElement {
+                tag: tag.to_string(),
+                attrs,
+                children,
+            }));
+        }
+        _ => {}
+    }
+}
+
+/// Remove whitespace-only text nodes between top-level blocks.
+fn trim_blank(nodes: Vec<Node>) -> Vec<Node> {
+    nodes.into_iter()
+        .filter(|node| !matches!(node, Node::Text(text) if text.trim().is_empty()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn nodes(html: &str) -> serde_json::Value {
+        serde_json::to_value(html_to_nodes(html)).unwrap()
+    }
+
+    #[test]
+    fn keeps_nested_elements() {
+        assert_eq!(
+            nodes("<p>Hello <b>bold <i>and italic</i></b></p>\n<ul><li>one</li><li>two <code>x</code></li></ul>"),
+            json!([
+                {"tag": "p", "children": ["Hello ", {"tag": "b", "children": ["bold ", {"tag": "i", "children": ["and italic"]}]}]},
+                {"tag": "ul", "children": [
+                    {"tag": "li", "children": ["one"]},
+                    {"tag": "li", "children": ["two ", {"tag": "code", "children": ["x"]}]},
+                ]},
+            ]),
+        );
+    }
+
+    #[test]
+    fn maps_or_unwraps_unsupported_tags() {
+        assert_eq!(
+            nodes("<h1>Title</h1><h5>Sub</h5><p><span>a <del>b</del></span></p><div>bare</div><div><p>wrapped</p></div>\
+                <script>alert(1)</script><style>p {}</style><table><tr><td>cell</td></tr></table>"),
+            json!([
+                {"tag": "h3", "children": ["Title"]},
+                {"tag": "h4", "children": ["Sub"]},
+                {"tag": "p", "children": ["a ", {"tag": "s", "children": ["b"]}]},
+                {"tag": "p", "children": ["bare"]},
+                {"tag": "p", "children": ["wrapped"]},
+                "cell",
+            ]),
+        );
+    }
+
+    #[test]
+    fn keeps_only_links_and_sources() {
+        assert_eq!(
+            nodes(r#"<p><a href="https://example.com" onclick="x()" class="c">link</a><a name="anchor">text</a></p>
+                <img src="https://example.com/a.png" alt="a" width="10"><img alt="no source"><video controls></video>"#),
+            json!([
+                {"tag": "p", "children": [
+                    {"tag": "a", "attrs": {"href": "https://example.com"}, "children": ["link"]},
+                    {"tag": "a", "children": ["text"]},
+                ]},
+                {"tag": "img", "attrs": {"src": "https://example.com/a.png"}},
+            ]),
+        );
+    }
+
+    #[test]
+    fn short_items_are_not_worth_a_page() {
+        let service = Service::new(Box::<StubClient>::default(), None, 20);
+
+        assert!(!service.is_long("<p>Short   and sweet</p>"));
+        assert!(service.is_long("<p>Twenty characters <b>or more</b></p>"));
+    }
+}
@@ -4,7 +4,7 @@ use sea_orm::prelude::*;
 use rssbot_entities::user;
 
 #[derive(Clone, Debug)]
-pub(crate) struct Service {
+pub struct Service {
     db: DatabaseConnection,
 }
 
@@ -12,6 +12,8 @@ pub(crate) struct Service {
 pub enum Error {
     #[error("Database error: {0}")]
     DatabaseError(#[from] DbErr),
+    #[error("User not found")]
+    UserNotFound,
 }
 
 impl Service {
@@ -28,12 +30,46 @@ impl Service {
         let user = user::ActiveModel {
             telegram_user_id: ActiveValue::Set(user_id),
             username: ActiveValue::Set(username),
+            max_subscriptions: ActiveValue::Set(None),
+            min_poll_interval_secs: ActiveValue::Set(None),
+            banned: ActiveValue::Set(false),
         }
             .insert(&self.db).await?;
 
         Ok(user)
     }
 
+    /// Override the configured subscription limit and poll interval for a user, `None` goes
+    /// back to the configured value.
+    #[tracing::instrument]
+    pub async fn set_limits(&self, user_id: i64, max_subscriptions: Option<i32>, min_poll_interval_secs: Option<i32>) -> Result<user::Model, Error> {
+        let user = user::Entity::find_by_id(user_id).one(&self.db).await?
+            .ok_or(Error::UserNotFound)?;
+
+        let mut act: user::ActiveModel = user.into();
+        act.max_subscriptions = ActiveValue::Set(max_subscriptions);
+        act.min_poll_interval_secs = ActiveValue::Set(min_poll_interval_secs);
+
+        Ok(act.update(&self.db).await?)
+    }
+
+    #[tracing::instrument]
+    pub async fn set_banned(&self, user_id: i64, banned: bool) -> Result<user::Model, Error> {
+        let user = user::Entity::find_by_id(user_id).one(&self.db).await?
+            .ok_or(Error::UserNotFound)?;
+
+        let mut act: user::ActiveModel = user.into();
+        act.banned = ActiveValue::Set(banned);
+
+        Ok(act.update(&self.db).await?)
+    }
+
+    /// Whether `user_id` is banned, unknown users are not.
+    #[tracing::instrument]
+    pub async fn is_banned(&self, user_id: i64) -> Result<bool, Error> {
+        Ok(user::Entity::find_by_id(user_id).one(&self.db).await?.is_some_and(|user| user.banned))
+    }
+
     #[tracing::instrument]
     pub async fn get_user_by_id(&self, user_id: i64) -> Result<Option<user::Model>, Error> {
         Ok(user::Entity::find_by_id(user_id).one(&self.db).await?)
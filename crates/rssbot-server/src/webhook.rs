@@ -0,0 +1,282 @@
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use axum::extract::{ConnectInfo, State};
+use axum::http::{HeaderMap, HeaderName, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use ipnet::IpNet;
+use teloxide::prelude::*;
+use teloxide::types::{AllowedUpdate, InputFile};
+use teloxide::update_listeners::UpdateListener;
+use teloxide::update_listeners::webhooks::{axum_no_setup, Options};
+
+use rssbot_common::config::Config;
+
+/// IP ranges Telegram sends webhook requests from.
+///
+/// See <https://core.telegram.org/bots/webhooks#the-short-version>.
+const TELEGRAM_IP_RANGES: [&str; 2] = ["149.154.160.0/20", "91.108.4.0/22"];
+
+/// Webhook listener settings, validated from the configuration.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub address: SocketAddr,
+    pub url: reqwest::Url,
+    /// Shared by every replica, each one registers it with Telegram and checks it on requests.
+    pub secret_token: String,
+    pub certificate: Option<PathBuf>,
+    pub max_connections: Option<u8>,
+    pub allowed_updates: Vec<AllowedUpdate>,
+    pub allowed_ips: Vec<IpNet>,
+    pub real_ip_header: Option<HeaderName>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("WEBHOOK_URL must be set when UPDATE_SOURCE is `webhook`")]
+    MissingUrl,
+    #[error("WEBHOOK_SECRET_TOKEN must be set when UPDATE_SOURCE is `webhook`")]
+    MissingSecretToken,
+    #[error("Invalid webhook address {0:?}")]
+    InvalidAddress(String),
+    #[error("Invalid webhook URL {0:?}")]
+    InvalidUrl(String),
+    #[error("Webhook secret token must be 1-256 characters of `A-Z`, `a-z`, `0-9`, `_` and `-`")]
+    InvalidSecretToken,
+    #[error("Webhook max connections must be in range 1..=100, got {0}")]
+    InvalidMaxConnections(u8),
+    #[error("Unknown update type {0:?}")]
+    InvalidAllowedUpdate(String),
+    #[error("Invalid IP range {0:?}")]
+    InvalidIpRange(String),
+    #[error("Invalid header name {0:?}")]
+    InvalidHeaderName(String),
+    #[error("Failed to set webhook: {0}")]
+    SetWebhook(#[from] teloxide::RequestError),
+    #[error("Failed to bind webhook server: {0}")]
+    Bind(#[source] Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl Settings {
+    pub fn from_config(config: &Config) -> Result<Self, Error> {
+        let url = config.webhook_url.as_deref().ok_or(Error::MissingUrl)?;
+
+        // a token generated on startup would differ between replicas, and Telegram only knows
+        // the one registered last
+        let secret_token = config.webhook_secret_token.clone().ok_or(Error::MissingSecretToken)?;
+        let valid_chars = secret_token.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'_' || c == b'-');
+        if !(1..=256).contains(&secret_token.len()) || !valid_chars {
+            return Err(Error::InvalidSecretToken);
+        }
+
+        if let Some(max_connections) = config.webhook_max_connections {
+            if !(1..=100).contains(&max_connections) {
+                return Err(Error::InvalidMaxConnections(max_connections));
+            }
+        }
+
+        let allowed_updates = config.webhook_allowed_updates.iter()
+            .map(|update| {
+                serde_json::from_value(serde_json::Value::String(update.clone()))
+                    .map_err(|_| Error::InvalidAllowedUpdate(update.clone()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // `telegram` stands for the ranges Telegram sends webhook requests from
+        let allowed_ips = config.webhook_allowed_ips.iter()
+            .flat_map(|range| match range.as_str() {
+                "telegram" => TELEGRAM_IP_RANGES.iter().map(|range| range.to_string()).collect(),
+                range => vec![range.to_string()],
+            })
+            .map(|range| parse_ip_range(&range).ok_or(Error::InvalidIpRange(range)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let real_ip_header = config.webhook_real_ip_header.as_deref()
+            .map(|name| name.parse().map_err(|_| Error::InvalidHeaderName(name.to_string())))
+            .transpose()?;
+
+        Ok(Self {
+            address: config.webhook_address.parse().map_err(|_| Error::InvalidAddress(config.webhook_address.clone()))?,
+            url: url.parse().map_err(|_| Error::InvalidUrl(url.to_string()))?,
+            secret_token,
+            certificate: config.webhook_certificate_path.clone().map(PathBuf::from),
+            max_connections: config.webhook_max_connections,
+            allowed_updates,
+            allowed_ips,
+            real_ip_header,
+        })
+    }
+}
+
+/// Accept both CIDR ranges and single addresses.
+pub(crate) fn parse_ip_range(range: &str) -> Option<IpNet> {
+    range.parse::<IpNet>().ok()
+        .or_else(|| range.parse::<IpAddr>().ok().map(IpNet::from))
+}
+
+/// Start the HTTP server receiving updates and register the webhook with Telegram.
+///
+/// `routes` are served by the same server, next to the webhook route. The address is bound
+/// first, so that Telegram isn't pointed at a server that failed to start.
+///
+/// Unlike `teloxide::update_listeners::webhooks::axum`, the webhook is not deleted when the
+/// listener stops, so that replicas shutting down during a rolling deployment don't cut off
+/// the ones still running.
+pub async fn listen(bot: Bot, settings: Settings, routes: Option<Router>) -> Result<impl UpdateListener<Err = Infallible>, Error> {
+    let server = axum::Server::try_bind(&settings.address)
+        .map_err(|err| Error::Bind(Box::new(err)))?;
+
+    let options = Options::new(settings.address, settings.url.clone())
+        .secret_token(settings.secret_token.clone());
+
+    let mut request = bot.set_webhook(settings.url.clone())
+        .secret_token(settings.secret_token.clone())
+        .allowed_updates(settings.allowed_updates.clone());
+    if let Some(path) = settings.certificate.clone() {
+        request = request.certificate(InputFile::file(path));
+    }
+    if let Some(max_connections) = settings.max_connections {
+        request = request.max_connections(max_connections);
+    }
+    request.await?;
+
+    tracing::info!("Webhook set to {}", settings.url);
+
+    let (mut listener, stop_flag, router) = axum_no_setup(options);
+    let router = if settings.allowed_ips.is_empty() {
+        router
+    } else {
+        router.layer(axum::middleware::from_fn_with_state(Arc::new(settings.clone()), filter_ip))
+    };
+    let router = match routes {
+        Some(routes) => router.merge(routes),
+        None => router,
+    };
+
+    let stop_token = listener.stop_token();
+
+    tokio::spawn(async move {
+        let result = server
+            .serve(router.into_make_service_with_connect_info::<SocketAddr>())
+            .with_graceful_shutdown(stop_flag)
+            .await;
+
+        if let Err(err) = result {
+            tracing::error!("Webhook server error: {}", err);
+            stop_token.stop();
+        }
+    });
+
+    Ok(listener)
+}
+
+/// Reject requests from addresses outside of the allow-list.
+async fn filter_ip<B>(
+    State(settings): State<Arc<Settings>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let client_ip = client_ip(request.headers(), settings.real_ip_header.as_ref(), peer);
+
+    match client_ip {
+        Some(ip) if settings.allowed_ips.iter().any(|range| range.contains(&ip)) => next.run(request).await,
+        _ => {
+            tracing::warn!("Rejected webhook request from {:?} (peer {})", client_ip, peer);
+            StatusCode::FORBIDDEN.into_response()
+        }
+    }
+}
+
+/// Resolve the address of the client that sent a webhook request.
+///
+/// Behind a reverse proxy the address comes from a header set by the proxy. Proxies append the
+/// address they saw to whatever the client sent, so only the last entry can be trusted.
+fn client_ip(headers: &HeaderMap, real_ip_header: Option<&HeaderName>, peer: SocketAddr) -> Option<IpAddr> {
+    match real_ip_header {
+        Some(header) => headers
+            .get_all(header)
+            .iter()
+            .next_back()
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit(',').next())
+            .and_then(|ip| ip.trim().parse().ok()),
+        None => Some(peer.ip()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+    use rssbot_test_support::MockBotApi;
+
+    use super::*;
+
+    const PEER: &str = "10.0.0.2:41000";
+
+    fn resolve(values: &[&str]) -> Option<IpAddr> {
+        let header = HeaderName::from_static("x-forwarded-for");
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(header.clone(), HeaderValue::from_str(value).unwrap());
+        }
+        client_ip(&headers, Some(&header), PEER.parse().unwrap())
+    }
+
+    #[test]
+    fn uses_the_entry_appended_by_the_proxy() {
+        assert_eq!(resolve(&["149.154.160.1"]), Some("149.154.160.1".parse().unwrap()));
+        // a client claiming to be Telegram is still identified by the address the proxy saw
+        assert_eq!(resolve(&["149.154.160.1, 203.0.113.7"]), Some("203.0.113.7".parse().unwrap()));
+        assert_eq!(resolve(&["149.154.160.1", "203.0.113.7"]), Some("203.0.113.7".parse().unwrap()));
+        assert_eq!(resolve(&["149.154.160.1, not an ip"]), None);
+        assert_eq!(resolve(&[]), None);
+    }
+
+    fn settings(address: SocketAddr) -> Settings {
+        Settings {
+            address,
+            url: "https://bot.example.com/webhook".parse().unwrap(),
+            secret_token: "shared-secret".to_string(),
+            certificate: None,
+            max_connections: None,
+            allowed_updates: Vec::new(),
+            allowed_ips: Vec::new(),
+            real_ip_header: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn registers_the_configured_secret_token() {
+        let api = MockBotApi::start();
+
+        listen(api.bot(), settings("127.0.0.1:0".parse().unwrap()), None).await.unwrap();
+
+        let calls = api.calls_to("setWebhook");
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].params["secret_token"], "shared-secret");
+    }
+
+    #[tokio::test]
+    async fn webhook_is_not_set_when_the_address_is_taken() {
+        let api = MockBotApi::start();
+        let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+
+        let err = listen(api.bot(), settings(taken.local_addr().unwrap()), None).await.err().unwrap();
+
+        assert!(matches!(err, Error::Bind(_)), "{}", err);
+        assert!(api.calls_to("setWebhook").is_empty());
+    }
+
+    #[test]
+    fn uses_the_peer_without_a_proxy_header() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", HeaderValue::from_static("149.154.160.1"));
+
+        assert_eq!(client_ip(&headers, None, PEER.parse().unwrap()), Some("10.0.0.2".parse().unwrap()));
+    }
+}
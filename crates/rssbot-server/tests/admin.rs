@@ -0,0 +1,144 @@
+use rssbot_test_support::{updates, Fixture};
+
+use common::{Harness, ADMIN};
+
+mod common;
+
+const USER: i64 = 6001;
+const OTHER: i64 = 6002;
+
+async fn reply(harness: &Harness, user_id: i64, text: &str) -> String {
+    harness.dispatch(updates::private_message(user_id, text)).await;
+    harness.api.calls_to("sendMessage").pop().unwrap().text().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn admin_commands_are_only_for_admins() {
+    let harness = Harness::new().await;
+    harness.user(USER).await;
+
+    harness.dispatch_unhandled(updates::private_message(USER, "/stats")).await;
+
+    let reply = reply(&harness, ADMIN, "/stats").await;
+    assert!(reply.starts_with("Users: 1 (0 banned)\nSubscriptions: 0 to 0 feeds"), "{}", reply);
+}
+
+#[tokio::test]
+async fn subscriptions_of_every_user_can_be_searched_and_synced() {
+    let harness = Harness::new().await;
+    harness.feeds.serve("/blog.xml", Fixture::file("feeds/blog.xml"));
+    harness.user(USER).await;
+    harness.user(OTHER).await;
+    let blog = harness.subscriptions.add_subscription(USER, USER, harness.feeds.url("/blog.xml"), 0).await.unwrap();
+    harness.subscriptions.add_subscription(OTHER, OTHER, harness.feeds.url("/news.xml"), 0).await.unwrap();
+
+    let listing = reply(&harness, ADMIN, "/subscriptions").await;
+    assert!(listing.starts_with("<b>Latest 2 subscriptions:</b>"), "{}", listing);
+
+    let listing = reply(&harness, ADMIN, "/subscriptions blog").await;
+    assert!(listing.contains(&format!("ID {}:", blog.id)) && !listing.contains("news.xml"), "{}", listing);
+    let listing = reply(&harness, ADMIN, &format!("/subscriptions {}", OTHER)).await;
+    assert!(listing.contains("news.xml") && !listing.contains("blog.xml"), "{}", listing);
+
+    let synced = reply(&harness, ADMIN, &format!("/forcesync {}", blog.id)).await;
+    assert!(synced.starts_with("Synced"), "{}", synced);
+    assert_eq!(harness.feeds.hits("/blog.xml"), 1);
+    assert_eq!(reply(&harness, ADMIN, "/forcesync 999").await, "Subscription not found");
+}
+
+#[tokio::test]
+async fn disabled_feeds_are_not_synced_or_subscribed_to() {
+    let harness = Harness::new().await;
+    harness.feeds.serve("/blog.xml", Fixture::file("feeds/blog.xml"));
+    harness.user(USER).await;
+    harness.user(OTHER).await;
+    let url = harness.feeds.url("/blog.xml");
+    let created = harness.subscriptions.add_subscription(USER, USER, url.clone(), 0).await.unwrap();
+
+    let disabled = reply(&harness, ADMIN, &format!("/disable {}", created.id)).await;
+    assert_eq!(disabled, format!("Disabled {} for 1 subscriptions", url));
+
+    harness.sync().await;
+    assert_eq!(harness.feeds.hits("/blog.xml"), 0);
+    for variant in [url.clone(), format!("{}/", url), format!("{}?utm_source=bot", url)] {
+        let err = harness.subscriptions.add_subscription(OTHER, OTHER, variant, 0).await.unwrap_err();
+        assert_eq!(err.to_string(), "This feed has been disabled by the administrators");
+    }
+    // another query names another feed on the same path
+    harness.subscriptions.add_subscription(OTHER, OTHER, format!("{}?ref=other", url), 0).await.unwrap();
+    harness.sync().await;
+    assert_eq!(harness.feeds.hits("/blog.xml"), 1);
+
+    reply(&harness, ADMIN, &format!("/enable {}", url)).await;
+    harness.sync().await;
+    // both feeds on the path this time
+    assert_eq!(harness.feeds.hits("/blog.xml"), 3);
+}
+
+#[tokio::test]
+async fn feeds_without_subscriptions_can_be_disabled() {
+    let harness = Harness::new().await;
+    harness.user(USER).await;
+    let url = harness.feeds.url("/blog.xml");
+
+    assert_eq!(reply(&harness, ADMIN, &format!("/disable {}", url)).await, format!("Disabled {} for 0 subscriptions", url));
+    assert!(harness.subscriptions.add_subscription(USER, USER, url.clone(), 0).await.is_err());
+
+    assert_eq!(reply(&harness, ADMIN, &format!("/enable {}/", url)).await, format!("Enabled {}/ for 0 subscriptions", url));
+    harness.subscriptions.add_subscription(USER, USER, url, 0).await.unwrap();
+}
+
+#[tokio::test]
+async fn banned_users_are_ignored() {
+    let harness = Harness::new().await;
+    harness.feeds.serve("/blog.xml", Fixture::file("feeds/blog.xml"));
+    harness.user(USER).await;
+    harness.subscriptions.add_subscription(USER, USER, harness.feeds.url("/blog.xml"), 0).await.unwrap();
+
+    assert_eq!(reply(&harness, ADMIN, &format!("/ban {}", USER)).await, format!("Banned user{0} ({0})", USER));
+    assert_eq!(reply(&harness, ADMIN, &format!("/ban {}", ADMIN)).await, "Administrators can't be banned");
+
+    let sent = harness.api.calls_to("sendMessage").len();
+    harness.dispatch(updates::private_message(USER, "/list")).await;
+    assert_eq!(harness.api.calls_to("sendMessage").len(), sent);
+    harness.sync().await;
+    assert_eq!(harness.feeds.hits("/blog.xml"), 0);
+
+    reply(&harness, ADMIN, &format!("/unban {}", USER)).await;
+    harness.sync().await;
+    assert_eq!(harness.feeds.hits("/blog.xml"), 1);
+}
+
+#[tokio::test]
+async fn limits_can_be_set_per_user() {
+    let harness = Harness::new().await;
+    harness.user(USER).await;
+
+    let set = reply(&harness, ADMIN, &format!("/limits {} 0 300", USER)).await;
+    assert_eq!(set, format!("Limits of user{0} ({0}): unlimited subscriptions, polled every 300 seconds", USER));
+    let user = harness.users.get_user_by_id(USER).await.unwrap().unwrap();
+    assert_eq!((user.max_subscriptions, user.min_poll_interval_secs), (Some(0), Some(300)));
+
+    reply(&harness, ADMIN, &format!("/limits {} default default", USER)).await;
+    let user = harness.users.get_user_by_id(USER).await.unwrap().unwrap();
+    assert_eq!((user.max_subscriptions, user.min_poll_interval_secs), (None, None));
+
+    assert!(reply(&harness, ADMIN, "/limits 6001 -1 default").await.starts_with("Usage:"));
+}
+
+#[tokio::test]
+async fn announcements_are_delivered_to_every_owner() {
+    let harness = Harness::new().await;
+    for (user, path) in [(USER, "/a.xml"), (USER, "/b.xml"), (OTHER, "/c.xml")] {
+        harness.user(user).await;
+        harness.subscriptions.add_subscription(user, user, harness.feeds.url(path), 0).await.unwrap();
+    }
+    harness.users.set_banned(OTHER, true).await.unwrap();
+
+    assert_eq!(reply(&harness, ADMIN, "/broadcast Maintenance tonight\nBack soon").await, "Announcement queued for 1 users");
+
+    let calls = harness.deliver(2).await;
+    let announcement = calls.last().unwrap();
+    assert_eq!(announcement.chat_id(), Some(USER));
+    assert_eq!(announcement.text(), Some("Maintenance tonight\nBack soon"));
+}
@@ -0,0 +1,156 @@
+#![allow(dead_code)] // each test binary uses a different part of the harness
+
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::time::Duration;
+
+use teloxide::dptree::di::DependencyMap;
+use teloxide::prelude::*;
+use tokio_util::sync::CancellationToken;
+
+use rssbot_server::dialogue::DialogueStorage;
+use rssbot_server::{credentials, feed, fetch, handlers};
+use rssbot_server::handlers::private::State;
+use rssbot_server::services::{admin, cache, delivery, extractor, subscription, user};
+use rssbot_test_support::{Call, FeedServer, MockBotApi, TestDatabase};
+
+/// How long to wait for something the bot does in the background.
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The configured administrator of the harness.
+pub const ADMIN: i64 = 1;
+
+/// A fetch policy that allows the local feed server.
+pub fn local_fetch_policy() -> fetch::Policy {
+    fetch::Policy {
+        allowed: vec!["127.0.0.0/8".parse().unwrap()].into(),
+        max_redirects: 3,
+        max_bytes: 1024 * 1024,
+        timeout: Duration::from_secs(5),
+        connect_timeout: Duration::from_secs(5),
+        user_agent: "rssbot-tests".to_string(),
+        proxy: None,
+        trace_propagation: Default::default(),
+    }
+}
+
+/// The cipher of the credentials of test subscriptions.
+pub fn cipher() -> credentials::Cipher {
+    credentials::Cipher::new(&[7; 32]).unwrap()
+}
+
+/// The bot wired to a mock Bot API, a feed server and a throwaway database.
+pub struct Harness {
+    pub api: MockBotApi,
+    pub feeds: FeedServer,
+    pub database: TestDatabase,
+    pub bot: Bot,
+    pub delivery: Arc<delivery::Service>,
+    pub subscriptions: Arc<subscription::Service>,
+    pub users: Arc<user::Service>,
+    pub admin: Arc<admin::Service>,
+    pub cache: Arc<cache::Service>,
+    pub storage: Arc<DialogueStorage<State>>,
+}
+
+impl Harness {
+    pub async fn new() -> Self {
+        Self::with_fetch_policy(local_fetch_policy()).await
+    }
+
+    pub async fn with_fetch_policy(fetch: fetch::Policy) -> Self {
+        Self::build(fetch, subscription::Limits::default()).await
+    }
+
+    pub async fn with_limits(limits: subscription::Limits) -> Self {
+        Self::build(local_fetch_policy(), limits).await
+    }
+
+    async fn build(fetch: fetch::Policy, limits: subscription::Limits) -> Self {
+        let api = MockBotApi::start();
+        let feeds = FeedServer::start();
+        let database = TestDatabase::new().await;
+        let db = database.connection();
+        let bot = api.bot();
+
+        let delivery = Arc::new(delivery::Service::new(db.clone(), bot.clone(), delivery::Options {
+            global_rate: 1000,
+            private_chat_interval: Duration::ZERO,
+            group_chat_interval: Duration::ZERO,
+            max_attempts: 3,
+            poll_interval: Duration::from_millis(20),
+            batch_size: 50,
+            retention: Some(Duration::from_secs(24 * 60 * 60)),
+        }));
+        let cache = Arc::new(cache::Service::local());
+        let extractor = Arc::new(extractor::Service::new(cache.clone(), extractor::Options {
+            max_bytes: 1024 * 1024,
+            max_chars: 4000,
+            cache_ttl: 60,
+            timeout: Duration::from_secs(5),
+        }, &fetch).unwrap());
+        let subscriptions = Arc::new(subscription::Service::new(db.clone(), delivery.clone(), extractor, None, feed::Options::default(), fetch, Some(cipher())).unwrap().with_limits(limits));
+        let users = Arc::new(user::Service::new(db.clone()));
+        let admin = Arc::new(admin::Service::new(db.clone(), delivery.clone(), [ADMIN]));
+        let storage = DialogueStorage::database(db);
+
+        Self { api, feeds, database, bot, delivery, subscriptions, users, admin, cache, storage }
+    }
+
+    pub async fn user(&self, user_id: i64) {
+        self.users.create_or_find_user(user_id, format!("user{}", user_id)).await.unwrap();
+    }
+
+    /// Run an update through the handler tree, failing the test if it is not handled.
+    pub async fn dispatch(&self, update: Update) {
+        match self.run_handlers(update).await {
+            ControlFlow::Break(Ok(())) => {}
+            ControlFlow::Break(Err(err)) => panic!("Handler failed: {:?}", err),
+            ControlFlow::Continue(_) => panic!("Update was not handled"),
+        }
+    }
+
+    /// Run an update through the handler tree, failing the test if it is handled.
+    pub async fn dispatch_unhandled(&self, update: Update) {
+        if let ControlFlow::Break(result) = self.run_handlers(update).await {
+            panic!("Update was handled: {:?}", result);
+        }
+    }
+
+    async fn run_handlers(&self, update: Update) -> ControlFlow<anyhow::Result<()>, DependencyMap> {
+        let deps = dptree::deps![
+            update,
+            self.bot.clone(),
+            self.api.me(),
+            self.storage.clone(),
+            self.subscriptions.clone(),
+            self.users.clone(),
+            self.admin.clone(),
+            self.cache.clone()
+        ];
+
+        handlers::schema().dispatch(deps).await
+    }
+
+    /// Run the delivery dispatcher until `count` messages were sent, or attempted.
+    pub async fn deliver(&self, count: usize) -> Vec<Call> {
+        let shutdown = CancellationToken::new();
+        let dispatcher = tokio::spawn({
+            let delivery = self.delivery.clone();
+            let shutdown = shutdown.clone();
+            async move { delivery.run(shutdown).await }
+        });
+
+        let calls = self.api.wait_for_calls("sendMessage", count, TIMEOUT).await;
+
+        // the batch in progress is recorded before the dispatcher returns
+        shutdown.cancel();
+        dispatcher.await.unwrap();
+
+        calls
+    }
+
+    pub async fn sync(&self) {
+        self.subscriptions.sync_subscriptions(&CancellationToken::new()).await.unwrap();
+    }
+}
@@ -0,0 +1,102 @@
+//! Feeds of the `corpus` fixture directory against their golden `.json` expectations.
+//!
+//! Run with `UPDATE_GOLDENS=1` to rewrite the expectations after an intended change, and
+//! review the diff.
+
+use std::path::Path;
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use reqwest::Url;
+use serde_json::{json, Value};
+
+use rssbot_server::feed;
+use rssbot_test_support::feed_server::fixture_path;
+use rssbot_test_support::{FeedServer, Fixture};
+
+/// Stands in for the address of the feed server in goldens.
+const FEED_SERVER: &str = "http://feeds.test";
+
+/// A fixed fetch time, for feeds without any date.
+fn fetched_at() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2024, 7, 1).unwrap().and_time(NaiveTime::MIN)
+}
+
+/// Fetch the feed the way `get_feed` does and normalise its items.
+async fn normalise(feeds: &FeedServer, name: &str) -> Value {
+    let path = format!("/{}", name);
+    feeds.serve(&path, Fixture::file(&format!("corpus/{}", name)));
+
+    let response = reqwest::get(feeds.url(&path)).await.unwrap();
+    let url = response.url().clone();
+    let content_type = response.headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .map(|value| value.to_str().unwrap().to_string());
+    let body = response.bytes().await.unwrap();
+    let feed = match feed::parse(&body, content_type.as_deref(), &url) {
+        Ok(feed) => feed,
+        Err(err) => return json!({ "error": err.to_string() }),
+    };
+
+    let items = feed::items(&feed, fetched_at(), &feed::Options::default())
+        .into_iter()
+        .map(|item| match item {
+            Ok(item) => json!({
+                "published": item.published.to_string(),
+                "dated_by": format!("{:?}", item.dated_by),
+                "title": item.title,
+                "link": item.link.as_str(),
+                "description": item.description,
+                "content": item.content,
+                "image": item.image.as_ref().map(Url::as_str),
+                "enclosure": item.enclosure.as_ref().map(Url::as_str),
+            }),
+            Err(skipped) => json!({ "skipped": skipped.to_string() }),
+        })
+        .collect::<Vec<_>>();
+
+    let metadata = feed::metadata(&feed);
+    json!({
+        "feed": {
+            "title": metadata.title,
+            "link": metadata.link.as_ref().map(Url::as_str),
+            "description": metadata.description,
+            "icon": metadata.icon.as_ref().map(Url::as_str),
+        },
+        "items": items,
+    })
+}
+
+#[tokio::test]
+async fn corpus_matches_goldens() {
+    let update = std::env::var_os("UPDATE_GOLDENS").is_some();
+    let feeds = FeedServer::start();
+    let directory = fixture_path("corpus");
+
+    let mut names = std::fs::read_dir(&directory).unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name.ends_with(".xml"))
+        .collect::<Vec<_>>();
+    names.sort();
+    assert!(!names.is_empty(), "No feeds in {}", directory.display());
+
+    let mut mismatches = Vec::new();
+    for name in names {
+        let actual = serde_json::to_string_pretty(&normalise(&feeds, &name).await).unwrap() + "\n";
+        // the server listens on a random port
+        let actual = actual.replace(&feeds.url(""), FEED_SERVER);
+        let golden = directory.join(Path::new(&name).with_extension("json"));
+
+        if update {
+            std::fs::write(&golden, &actual).unwrap();
+            continue;
+        }
+
+        match std::fs::read_to_string(&golden) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => mismatches.push(format!("{}:\n--- expected\n{}\n--- actual\n{}", name, expected, actual)),
+            Err(_) => mismatches.push(format!("{}: no golden at {}", name, golden.display())),
+        }
+    }
+
+    assert!(mismatches.is_empty(), "Rerun with UPDATE_GOLDENS=1 if the changes are intended.\n\n{}", mismatches.join("\n"));
+}
@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use sea_orm::EntityTrait;
+
+use rssbot_entities::subscription;
+use rssbot_server::fetch;
+use rssbot_test_support::{updates, Fixture};
+
+use common::{local_fetch_policy, Harness};
+
+mod common;
+
+const USER: i64 = 3001;
+
+fn public_only() -> fetch::Policy {
+    fetch::Policy { allowed: Vec::new().into(), ..local_fetch_policy() }
+}
+
+/// Subscribe to `url`, sync once and return the error recorded on the subscription.
+async fn sync_error(harness: &Harness, url: String) -> String {
+    harness.user(USER).await;
+    let subscription = harness.subscriptions.add_subscription(USER, USER, url, 1).await.unwrap();
+
+    harness.sync().await;
+
+    subscription::Entity::find_by_id(subscription.id)
+        .one(&harness.database.connection()).await.unwrap().unwrap()
+        .last_error
+        .expect("The sync should have failed")
+}
+
+#[tokio::test]
+async fn private_addresses_are_refused() {
+    let harness = Harness::with_fetch_policy(public_only()).await;
+    harness.feeds.serve("/blog.xml", Fixture::file("feeds/blog.xml"));
+
+    let error = sync_error(&harness, harness.feeds.url("/blog.xml")).await;
+
+    assert!(error.contains("Address is not allowed: 127.0.0.1"), "{}", error);
+    assert_eq!(harness.feeds.hits("/blog.xml"), 0);
+}
+
+#[tokio::test]
+async fn hosts_resolving_to_private_addresses_are_refused() {
+    let harness = Harness::with_fetch_policy(public_only()).await;
+    harness.feeds.serve("/blog.xml", Fixture::file("feeds/blog.xml"));
+    let url = harness.feeds.url("/blog.xml").replace("127.0.0.1", "localhost");
+
+    let error = sync_error(&harness, url).await;
+
+    assert!(error.contains("Host only resolves to addresses that are not allowed: localhost"), "{}", error);
+    assert_eq!(harness.feeds.hits("/blog.xml"), 0);
+}
+
+#[tokio::test]
+async fn every_redirect_is_checked() {
+    let harness = Harness::new().await;
+    harness.feeds.serve("/moved.xml", Fixture::redirect("http://169.254.169.254/latest/meta-data"));
+
+    let error = sync_error(&harness, harness.feeds.url("/moved.xml")).await;
+
+    assert!(error.contains("Address is not allowed: 169.254.169.254"), "{}", error);
+}
+
+#[tokio::test]
+async fn redirects_are_limited() {
+    let harness = Harness::new().await;
+    for hop in 0..4 {
+        harness.feeds.serve(&format!("/hop/{}", hop), Fixture::redirect(&format!("/hop/{}", hop + 1)));
+    }
+    harness.feeds.serve("/hop/4", Fixture::file("feeds/blog.xml"));
+
+    let error = sync_error(&harness, harness.feeds.url("/hop/0")).await;
+
+    assert!(error.contains("More than 3 redirects"), "{}", error);
+    assert_eq!(harness.feeds.hits("/hop/4"), 0);
+}
+
+#[tokio::test]
+async fn large_feeds_are_refused() {
+    let harness = Harness::with_fetch_policy(fetch::Policy { max_bytes: 100, ..local_fetch_policy() }).await;
+    harness.feeds.serve("/blog.xml", Fixture::file("feeds/blog.xml"));
+
+    let error = sync_error(&harness, harness.feeds.url("/blog.xml")).await;
+
+    assert!(error.contains("Response exceeds 100 bytes"), "{}", error);
+}
+
+#[tokio::test]
+async fn slow_feeds_time_out() {
+    let harness = Harness::with_fetch_policy(fetch::Policy { timeout: Duration::from_millis(200), ..local_fetch_policy() }).await;
+    harness.feeds.serve("/slow.xml", Fixture::file("feeds/blog.xml").with_delay(Duration::from_secs(2)));
+
+    let error = sync_error(&harness, harness.feeds.url("/slow.xml")).await;
+
+    assert!(error.contains("Request timed out"), "{}", error);
+}
+
+#[tokio::test]
+async fn subscribing_to_a_private_address_is_refused() {
+    let harness = Harness::with_fetch_policy(public_only()).await;
+
+    harness.dispatch(updates::private_message(USER, "/start")).await;
+    harness.dispatch(updates::private_message(USER, "/subscribe")).await;
+    harness.dispatch(updates::private_message(USER, "http://169.254.169.254/latest/meta-data")).await;
+
+    let reply = harness.api.calls_to("sendMessage").pop().unwrap();
+    assert!(reply.text().unwrap().starts_with("Can't subscribe to this URL: Address is not allowed"));
+}
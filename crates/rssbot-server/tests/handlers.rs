@@ -0,0 +1,118 @@
+use teloxide::dispatching::dialogue::Storage;
+use teloxide::types::ChatId;
+
+use rssbot_server::handlers::private::State;
+use rssbot_test_support::{updates, Fixture};
+
+use common::Harness;
+
+mod common;
+
+const USER: i64 = 2001;
+const GROUP: i64 = -100_2002;
+
+async fn state(harness: &Harness) -> Option<State> {
+    harness.storage.clone().get_dialogue(ChatId(USER)).await.unwrap()
+}
+
+#[tokio::test]
+async fn start_registers_the_user() {
+    let harness = Harness::new().await;
+
+    harness.dispatch(updates::private_message(USER, "/start")).await;
+
+    let user = harness.users.get_user_by_id(USER).await.unwrap().unwrap();
+    assert_eq!(user.username, format!("user{}", USER));
+
+    let calls = harness.api.calls_to("sendMessage");
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].chat_id(), Some(USER));
+    assert!(calls[0].text().unwrap().starts_with("Hello!"));
+}
+
+#[tokio::test]
+async fn subscribe_to_a_feed_for_a_group() {
+    let harness = Harness::new().await;
+    let url = harness.feeds.url("/blog.xml");
+
+    harness.dispatch(updates::private_message(USER, "/start")).await;
+    harness.dispatch(updates::private_message(USER, "/subscribe")).await;
+    assert!(matches!(state(&harness).await, Some(State::SubscribeWaitingUrl)));
+
+    harness.dispatch(updates::private_message(USER, &url)).await;
+    assert!(matches!(state(&harness).await, Some(State::SubscribeWaitingBackfill { url: waiting }) if waiting == url));
+    let choices = harness.api.calls_to("sendMessage").pop().unwrap();
+    assert_eq!(choices.params["reply_markup"]["inline_keyboard"].as_array().unwrap().len(), 4);
+
+    harness.dispatch(updates::callback_query(USER, "1")).await;
+    assert!(matches!(state(&harness).await, Some(State::Unstated)));
+    assert_eq!(harness.api.calls_to("answerCallbackQuery").len(), 1);
+
+    // the link adds the bot to a group with the session ID as start parameter
+    let link = harness.api.calls_to("sendMessage").pop().unwrap();
+    let session = link.text().unwrap()
+        .split("startgroup=").nth(1).unwrap()
+        .split(',').next().unwrap()
+        .to_string();
+
+    harness.dispatch(updates::group_message(GROUP, USER, &format!("/start {}", session))).await;
+
+    let subscriptions = harness.subscriptions.list_subscriptions_for_chat(GROUP).await.unwrap();
+    assert_eq!(subscriptions.len(), 1);
+    assert_eq!(subscriptions[0].url, url);
+    assert_eq!(subscriptions[0].user_refer, USER);
+    assert_eq!(subscriptions[0].initial_backfill, Some(1));
+
+    // the session can only be used once
+    assert_eq!(harness.cache.get(&session).await.unwrap(), None);
+
+    harness.dispatch(updates::group_message(GROUP, USER, "/list")).await;
+    let list = harness.api.calls_to("sendMessage").pop().unwrap();
+    assert_eq!(list.chat_id(), Some(GROUP));
+    assert!(list.text().unwrap().contains(&url));
+}
+
+#[tokio::test]
+async fn rename_a_subscription_and_list_it() {
+    let harness = Harness::new().await;
+    harness.dispatch(updates::private_message(USER, "/start")).await;
+    let subscription = harness.subscriptions.add_subscription(USER, USER, harness.feeds.url("/blog.xml"), 0).await.unwrap();
+
+    harness.dispatch(updates::private_message(USER, "/rename")).await;
+    let keyboard = harness.api.calls_to("sendMessage").pop().unwrap();
+    // without a fetch or a custom name, subscriptions go by their URL
+    assert_eq!(keyboard.params["reply_markup"]["inline_keyboard"][0][0]["text"], format!("{} -> {}", subscription.url, USER));
+
+    // an ID picked on another keyboard isn't taken for a rename
+    harness.dispatch(updates::callback_query(USER, &format!("fulltext:{}", subscription.id))).await;
+    assert!(matches!(state(&harness).await, Some(State::RenameWaitingCallbackQuery)));
+
+    harness.dispatch(updates::callback_query(USER, &format!("rename:{}", subscription.id))).await;
+    assert!(matches!(state(&harness).await, Some(State::RenameWaitingInput { id }) if id == subscription.id));
+    harness.dispatch(updates::private_message(USER, "News & <Notes>")).await;
+    assert!(matches!(state(&harness).await, Some(State::Unstated)));
+
+    harness.dispatch(updates::private_message(USER, "/list")).await;
+    let list = harness.api.calls_to("sendMessage").pop().unwrap();
+    assert!(list.text().unwrap().contains(&format!("ID {}: <a href=\"{}\">News &amp; &lt;Notes&gt;</a>", subscription.id, subscription.url)), "{:?}", list.text());
+}
+
+#[tokio::test]
+async fn list_shows_the_last_fetch_and_its_error() {
+    let harness = Harness::new().await;
+    harness.feeds.serve("/broken.xml", Fixture::status(500));
+    harness.dispatch(updates::private_message(USER, "/start")).await;
+    harness.subscriptions.add_subscription(USER, USER, harness.feeds.url("/broken.xml"), 0).await.unwrap();
+
+    harness.dispatch(updates::private_message(USER, "/list")).await;
+    let list = harness.api.calls_to("sendMessage").pop().unwrap();
+    assert!(list.text().unwrap().contains("Last Fetched: N/A\nLast Error: N/A"), "{:?}", list.text());
+
+    harness.sync().await;
+
+    harness.dispatch(updates::private_message(USER, "/list")).await;
+    let list = harness.api.calls_to("sendMessage").pop().unwrap();
+    let text = list.text().unwrap();
+    assert!(!text.contains("Last Fetched: N/A") && !text.contains("Last Error: N/A"), "{:?}", text);
+    assert!(text.contains("500"), "{:?}", text);
+}
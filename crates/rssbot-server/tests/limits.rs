@@ -0,0 +1,85 @@
+use std::time::Duration;
+
+use sea_orm::EntityTrait;
+
+use rssbot_entities::subscription;
+use rssbot_server::services::subscription::{Error, Limits};
+use rssbot_test_support::{updates, Fixture};
+
+use common::Harness;
+
+mod common;
+
+const USER: i64 = 5001;
+const GROUP: i64 = -5001;
+
+#[tokio::test]
+async fn users_and_chats_are_held_to_their_quotas() {
+    let harness = Harness::with_limits(Limits { per_user: 2, per_chat: 1, ..Limits::default() }).await;
+    harness.user(USER).await;
+    let subscriptions = &harness.subscriptions;
+
+    subscriptions.add_subscription(USER, USER, harness.feeds.url("/a.xml"), 0).await.unwrap();
+    let err = subscriptions.add_subscription(USER, USER, harness.feeds.url("/b.xml"), 0).await.unwrap_err();
+    assert!(matches!(err, Error::ChatQuotaExceeded(1)), "{:?}", err);
+
+    subscriptions.add_subscription(USER, GROUP, harness.feeds.url("/b.xml"), 0).await.unwrap();
+    let err = subscriptions.add_subscription(USER, -5002, harness.feeds.url("/c.xml"), 0).await.unwrap_err();
+    assert!(matches!(err, Error::UserQuotaExceeded(2)), "{:?}", err);
+
+    // asking for a feed is refused up front
+    harness.dispatch(updates::private_message(USER, "/subscribe")).await;
+    let reply = harness.api.calls_to("sendMessage").pop().unwrap();
+    assert!(reply.text().unwrap().starts_with("You have reached the limit of 2 subscriptions"), "{:?}", reply.text());
+
+    // an override of 0 lifts the limit for that user
+    harness.users.set_limits(USER, Some(0), None).await.unwrap();
+    subscriptions.add_subscription(USER, -5002, harness.feeds.url("/c.xml"), 0).await.unwrap();
+}
+
+#[tokio::test]
+async fn the_global_quota_counts_every_user() {
+    let harness = Harness::with_limits(Limits { global: 1, ..Limits::default() }).await;
+    harness.user(USER).await;
+    harness.user(USER + 1).await;
+
+    harness.subscriptions.add_subscription(USER, USER, harness.feeds.url("/a.xml"), 0).await.unwrap();
+    let err = harness.subscriptions.add_subscription(USER + 1, USER + 1, harness.feeds.url("/b.xml"), 0).await.unwrap_err();
+
+    assert!(matches!(err, Error::GlobalQuotaExceeded), "{:?}", err);
+}
+
+#[tokio::test]
+async fn feeds_are_not_fetched_more_often_than_the_poll_interval() {
+    let harness = Harness::with_limits(Limits { min_poll_interval: Duration::from_secs(3600), ..Limits::default() }).await;
+    harness.feeds.serve("/blog.xml", Fixture::file("feeds/blog.xml"));
+    harness.user(USER).await;
+    let created = harness.subscriptions.add_subscription(USER, USER, harness.feeds.url("/blog.xml"), 0).await.unwrap();
+
+    harness.sync().await;
+    harness.sync().await;
+    assert_eq!(harness.feeds.hits("/blog.xml"), 1);
+
+    let fetched = subscription::Entity::find_by_id(created.id)
+        .one(&harness.database.connection()).await.unwrap().unwrap().last_fetched;
+    assert!(fetched.is_some());
+
+    // the owner's own interval takes precedence
+    harness.users.set_limits(USER, None, Some(0)).await.unwrap();
+
+    harness.sync().await;
+    assert_eq!(harness.feeds.hits("/blog.xml"), 2);
+}
+
+#[tokio::test]
+async fn concurrent_subscriptions_do_not_exceed_the_quota() {
+    let harness = Harness::with_limits(Limits { per_user: 1, ..Limits::default() }).await;
+    harness.user(USER).await;
+
+    let add = |path: &str| harness.subscriptions.add_subscription(USER, USER, harness.feeds.url(path), 0);
+    let (a, b) = tokio::join!(add("/a.xml"), add("/b.xml"));
+
+    assert!(a.is_ok() || b.is_ok(), "{:?} {:?}", a, b);
+    let stored = subscription::Entity::find().all(&harness.database.connection()).await.unwrap();
+    assert_eq!(stored.len(), 1);
+}
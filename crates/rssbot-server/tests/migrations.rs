@@ -0,0 +1,90 @@
+use sea_orm::ConnectionTrait;
+
+use rssbot_migrator::schema::{self, Direction, Status};
+use rssbot_migrator::{Migrator, MigratorTrait, SchemaManager};
+use rssbot_test_support::TestDatabase;
+
+/// Columns added after SQLite support, as `(migration, table, column)`, which databases created by
+/// older binaries lack.
+const ADDED_COLUMNS: [(&str, &str, &str); 11] = [
+    ("m20261019_090000_add_subscription_credentials", "subscriptions", "credentials"),
+    ("m20261019_100000_add_subscription_feed_metadata", "subscriptions", "feed_title"),
+    ("m20261019_100000_add_subscription_feed_metadata", "subscriptions", "feed_link"),
+    ("m20261019_100000_add_subscription_feed_metadata", "subscriptions", "feed_description"),
+    ("m20261019_100000_add_subscription_feed_metadata", "subscriptions", "feed_icon"),
+    ("m20261019_100000_add_subscription_feed_metadata", "subscriptions", "display_name"),
+    ("m20261019_100000_add_subscription_feed_metadata", "subscriptions", "name_header"),
+    ("m20261019_110000_add_limits", "users", "max_subscriptions"),
+    ("m20261019_110000_add_limits", "users", "min_poll_interval_secs"),
+    ("m20261019_110000_add_limits", "subscriptions", "last_fetched"),
+    ("m20261019_120000_add_moderation", "users", "banned"),
+];
+
+#[tokio::test]
+async fn migrations_can_be_rolled_back_and_applied_again() {
+    let database = TestDatabase::new().await;
+    let db = database.connection();
+    let manager = SchemaManager::new(&db);
+
+    // down to the table created by the first migration, whose later columns are then dropped
+    Migrator::down(&db, Some(Migrator::migrations().len() as u32 - 1)).await.unwrap();
+    assert!(!manager.has_column("subscriptions", "initial_backfill").await.unwrap());
+
+    Migrator::up(&db, None).await.unwrap();
+    for column in ["initial_backfill", "full_text", "telegraph"] {
+        assert!(manager.has_column("subscriptions", column).await.unwrap(), "{}", column);
+    }
+    for (_, table, column) in ADDED_COLUMNS {
+        assert!(manager.has_column(table, column).await.unwrap(), "{}.{}", table, column);
+    }
+    assert!(manager.has_table("disabled_feeds").await.unwrap());
+}
+
+#[tokio::test]
+async fn databases_created_by_older_binaries_get_the_new_columns() {
+    let database = TestDatabase::new().await;
+    let db = database.connection();
+
+    // as if the first migration had created the tables with the entities of an older binary
+    for (migration, table, column) in ADDED_COLUMNS {
+        db.execute_unprepared(&format!("ALTER TABLE {} DROP COLUMN {}", table, column)).await.unwrap();
+        db.execute_unprepared(&format!("DELETE FROM seaql_migrations WHERE version = '{}'", migration)).await.unwrap();
+    }
+    db.execute_unprepared("DROP TABLE disabled_feeds").await.unwrap();
+
+    Migrator::up(&db, None).await.unwrap();
+
+    let manager = SchemaManager::new(&db);
+    for (_, table, column) in ADDED_COLUMNS {
+        assert!(manager.has_column(table, column).await.unwrap(), "{}.{}", table, column);
+    }
+    assert!(manager.has_table("disabled_feeds").await.unwrap());
+}
+
+#[tokio::test]
+async fn dry_runs_plan_every_migration_without_running_it() {
+    let database = TestDatabase::new().await;
+    let db = database.connection();
+    let manager = SchemaManager::new(&db);
+    let names = Migrator::migrations().iter().map(|migration| migration.name().to_string()).collect::<Vec<_>>();
+
+    let plan = schema::dry_run(&db, Direction::Down, Some(names.len() as u32)).await.unwrap();
+    assert_eq!(plan.iter().rev().map(|(name, _)| name).collect::<Vec<_>>(), names.iter().collect::<Vec<_>>());
+    for (name, statements) in &plan {
+        assert!(!statements.is_empty(), "{}", name);
+    }
+    // the column checks saw the real schema, so the columns are dropped
+    let (_, statements) = plan.iter().find(|(name, _)| name == "m20261019_120000_add_moderation").unwrap();
+    assert!(statements.iter().any(|statement| statement.contains("DROP COLUMN \"banned\"")), "{:?}", statements);
+    assert!(manager.has_column("users", "banned").await.unwrap());
+
+    Migrator::reset(&db).await.unwrap();
+
+    let plan = schema::dry_run(&db, Direction::Up, None).await.unwrap();
+    assert_eq!(plan.iter().map(|(name, _)| name).collect::<Vec<_>>(), names.iter().collect::<Vec<_>>());
+    for (name, statements) in &plan {
+        assert!(!statements.is_empty(), "{}", name);
+    }
+    assert!(!manager.has_table("subscriptions").await.unwrap());
+    assert!(schema::status(&db).await.unwrap().iter().all(|status| matches!(status, Status::Pending(_))));
+}
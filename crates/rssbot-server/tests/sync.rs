@@ -0,0 +1,284 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use sea_orm::{ActiveModelTrait, ActiveValue, EntityTrait};
+use tokio_util::sync::CancellationToken;
+
+use rssbot_entities::{delivery, subscription};
+use rssbot_server::services::delivery::{self as queue, NewDelivery};
+use rssbot_test_support::{Fixture, Reply};
+
+use common::Harness;
+
+mod common;
+
+const USER: i64 = 1001;
+const CHANNEL: i64 = -100_2002;
+
+async fn subscribe(harness: &Harness, path: &str, backfill: i32) -> subscription::Model {
+    harness.user(USER).await;
+    harness.subscriptions.add_subscription(USER, USER, harness.feeds.url(path), backfill).await.unwrap()
+}
+
+async fn deliveries(harness: &Harness) -> Vec<delivery::Model> {
+    delivery::Entity::find().all(&harness.database.connection()).await.unwrap()
+}
+
+async fn reload(harness: &Harness, subscription: &subscription::Model) -> subscription::Model {
+    subscription::Entity::find_by_id(subscription.id).one(&harness.database.connection()).await.unwrap().unwrap()
+}
+
+fn message(chat: i64, text: &str) -> NewDelivery {
+    NewDelivery {
+        subscription_id: None,
+        target_chat: chat,
+        text: text.to_string(),
+        parse_mode: None,
+        button: None,
+    }
+}
+
+/// An RSS document with an item per `(title, pubDate)`, newest first like most feeds.
+fn feed(items: &[(&str, &str)]) -> Fixture {
+    let items = items.iter()
+        .map(|(title, date)| format!(
+            "<item><title>{}</title><link>https://blog.example.com/{}</link><description>Post</description><pubDate>{}</pubDate></item>",
+            title, title, date,
+        ))
+        .collect::<String>();
+    Fixture::rss(format!("<?xml version=\"1.0\"?><rss version=\"2.0\"><channel><title>Blog</title>{}</channel></rss>", items))
+}
+
+#[tokio::test]
+async fn backfilled_items_are_sent_oldest_first() {
+    let harness = Harness::new().await;
+    harness.feeds.serve("/blog.xml", Fixture::file("feeds/blog.xml"));
+    let subscription = subscribe(&harness, "/blog.xml", 2).await;
+
+    harness.sync().await;
+    let calls = harness.deliver(2).await;
+
+    assert_eq!(calls.len(), 2);
+    assert!(calls.iter().all(|call| call.chat_id() == Some(USER)));
+    assert!(calls[0].text().unwrap().contains("Second post"));
+    assert!(calls[1].text().unwrap().contains("Third post"));
+    assert_eq!(calls[0].params["parse_mode"], "MarkdownV2");
+    assert_eq!(calls[0].params["reply_markup"]["inline_keyboard"][0][0]["url"], "https://blog.example.com/second");
+
+    let subscription = reload(&harness, &subscription).await;
+    assert_eq!(subscription.last_error, None);
+    assert_eq!(subscription.initial_backfill, None);
+    // the cursor is the newest item sent, not the time of the sync
+    let newest = chrono::NaiveDate::from_ymd_opt(2024, 6, 5).unwrap().and_hms_opt(12, 0, 0).unwrap();
+    assert_eq!(subscription.last_updated, newest);
+
+    // nothing new on the next run
+    harness.sync().await;
+    assert_eq!(deliveries(&harness).await.len(), 2);
+    assert_eq!(harness.feeds.hits("/blog.xml"), 2);
+}
+
+#[tokio::test]
+async fn retry_after_is_honoured() {
+    let harness = Harness::new().await;
+    harness.feeds.serve("/blog.xml", Fixture::file("feeds/blog.xml"));
+    harness.api.reply_next("sendMessage", Reply::RetryAfter(1));
+    subscribe(&harness, "/blog.xml", 1).await;
+
+    harness.sync().await;
+    let calls = harness.deliver(2).await;
+
+    assert_eq!(calls[0].params, calls[1].params);
+
+    let deliveries = deliveries(&harness).await;
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].status, delivery::Status::Sent);
+    // waiting for the rate limit is not a failed attempt
+    assert_eq!(deliveries[0].attempts, 1);
+}
+
+#[tokio::test]
+async fn migrated_chats_are_followed_by_the_subscription() {
+    let harness = Harness::new().await;
+    harness.feeds.serve("/blog.xml", Fixture::file("feeds/blog.xml"));
+    harness.api.reply_next("sendMessage", Reply::MigrateToChatId(-100_1001));
+    let subscription = subscribe(&harness, "/blog.xml", 1).await;
+
+    harness.sync().await;
+    let calls = harness.deliver(2).await;
+
+    assert_eq!(calls[1].chat_id(), Some(-100_1001));
+    assert_eq!(deliveries(&harness).await[0].target_chat, -100_1001);
+    assert_eq!(reload(&harness, &subscription).await.target_chat, -100_1001);
+}
+
+#[tokio::test]
+async fn blocked_chat_fails_the_delivery() {
+    let harness = Harness::new().await;
+    harness.feeds.serve("/blog.xml", Fixture::file("feeds/blog.xml"));
+    harness.api.reply_next("sendMessage", Reply::blocked());
+    let subscription = subscribe(&harness, "/blog.xml", 1).await;
+
+    harness.sync().await;
+    harness.deliver(1).await;
+
+    let deliveries = deliveries(&harness).await;
+    assert_eq!(deliveries[0].status, delivery::Status::Failed);
+    assert!(deliveries[0].last_error.as_deref().unwrap().contains("blocked"));
+
+    let subscription = reload(&harness, &subscription).await;
+    assert!(subscription.last_error.unwrap().starts_with("Delivery failed"));
+}
+
+#[tokio::test]
+async fn deliveries_claimed_by_another_dispatcher_are_left_until_the_claim_expires() {
+    let harness = Harness::new().await;
+    let db = harness.database.connection();
+
+    for (text, claimed_for) in [("held", chrono::Duration::minutes(5)), ("abandoned", chrono::Duration::minutes(-5))] {
+        let queued = harness.delivery.enqueue(message(USER, text)).await.unwrap();
+
+        let mut act: delivery::ActiveModel = queued.into();
+        act.status = ActiveValue::Set(delivery::Status::Sending);
+        act.next_attempt_at = ActiveValue::Set(chrono::Utc::now().naive_utc() + claimed_for);
+        act.update(&db).await.unwrap();
+    }
+
+    let calls = harness.deliver(1).await;
+
+    // the dispatcher stopped once the first message went out, nothing else was due
+    assert_eq!(harness.api.calls_to("sendMessage").len(), 1);
+    assert_eq!(calls[0].text(), Some("abandoned"));
+    let deliveries = deliveries(&harness).await;
+    assert_eq!(deliveries[0].status, delivery::Status::Sending);
+    assert_eq!(deliveries[1].status, delivery::Status::Sent);
+}
+
+#[tokio::test]
+async fn a_backlog_for_one_chat_does_not_hold_up_the_others() {
+    let harness = Harness::new().await;
+    let delivery = Arc::new(queue::Service::new(harness.database.connection(), harness.api.bot(), queue::Options {
+        global_rate: 1000,
+        private_chat_interval: Duration::ZERO,
+        group_chat_interval: Duration::from_secs(60 * 60),
+        max_attempts: 3,
+        poll_interval: Duration::from_millis(20),
+        batch_size: 2,
+        retention: None,
+    }));
+    for (chat, text) in [(CHANNEL, "first"), (CHANNEL, "second"), (CHANNEL, "third"), (USER, "private")] {
+        delivery.enqueue(message(chat, text)).await.unwrap();
+    }
+
+    let shutdown = CancellationToken::new();
+    let dispatcher = tokio::spawn({
+        let delivery = delivery.clone();
+        let shutdown = shutdown.clone();
+        async move { delivery.run(shutdown).await }
+    });
+    let calls = harness.api.wait_for_calls("sendMessage", 2, Duration::from_secs(10)).await;
+    shutdown.cancel();
+    dispatcher.await.unwrap();
+
+    // the channel waits for its next slot, its backlog is left out of the following batches
+    assert_eq!(calls[0].text(), Some("first"));
+    assert_eq!(calls[1].text(), Some("private"));
+    assert_eq!(delivery.count(delivery::Status::Pending).await.unwrap(), 2);
+}
+
+#[tokio::test]
+async fn sent_deliveries_are_purged_after_the_retention() {
+    let harness = Harness::new().await;
+    let db = harness.database.connection();
+
+    for (text, sent_ago) in [("old", chrono::Duration::days(2)), ("recent", chrono::Duration::hours(1))] {
+        let mut act: delivery::ActiveModel = harness.delivery.enqueue(message(USER, text)).await.unwrap().into();
+        act.status = ActiveValue::Set(delivery::Status::Sent);
+        act.sent_at = ActiveValue::Set(Some(chrono::Utc::now().naive_utc() - sent_ago));
+        act.update(&db).await.unwrap();
+    }
+    let mut act: delivery::ActiveModel = harness.delivery.enqueue(message(USER, "failed")).await.unwrap().into();
+    act.status = ActiveValue::Set(delivery::Status::Failed);
+    act.update(&db).await.unwrap();
+
+    assert_eq!(harness.delivery.purge_sent().await.unwrap(), 1);
+    let kept = deliveries(&harness).await.into_iter().map(|delivery| delivery.text).collect::<Vec<_>>();
+    assert_eq!(kept, ["recent", "failed"]);
+}
+
+#[tokio::test]
+async fn feed_errors_are_recorded() {
+    let harness = Harness::new().await;
+    harness.feeds.serve("/broken.xml", Fixture::status(500));
+    let subscription = subscribe(&harness, "/broken.xml", 1).await;
+
+    harness.sync().await;
+
+    let subscription = reload(&harness, &subscription).await;
+    assert!(subscription.last_error.unwrap().contains("500"));
+    assert!(deliveries(&harness).await.is_empty());
+    assert!(harness.api.calls().is_empty());
+}
+
+#[tokio::test]
+async fn undated_items_only_count_towards_the_backfill() {
+    let harness = Harness::new().await;
+    harness.feeds.serve("/undated.xml", Fixture::file("corpus/undated.xml"));
+    subscribe(&harness, "/undated.xml", 1).await;
+
+    harness.sync().await;
+    let calls = harness.deliver(1).await;
+    assert!(calls[0].text().unwrap().contains("Newest"));
+
+    // dated by the fetch time again, but not new
+    harness.sync().await;
+    assert_eq!(deliveries(&harness).await.len(), 1);
+}
+
+#[tokio::test]
+async fn feed_metadata_names_the_subscription() {
+    let harness = Harness::new().await;
+    harness.feeds.serve("/blog.xml", Fixture::file("feeds/blog.xml"));
+    let subscription = subscribe(&harness, "/blog.xml", 1).await;
+    harness.subscriptions.set_name_header(USER, subscription.id, true).await.unwrap();
+
+    harness.sync().await;
+
+    let subscription = reload(&harness, &subscription).await;
+    assert_eq!(subscription.feed_title.as_deref(), Some("Example Blog"));
+    assert_eq!(subscription.feed_link.as_deref(), Some("https://blog.example.com/"));
+    assert_eq!(subscription.feed_description.as_deref(), Some("Posts from the example blog"));
+    assert_eq!(subscription.name(), "Example Blog");
+
+    let calls = harness.deliver(1).await;
+    assert!(calls[0].text().unwrap().starts_with("_Example Blog_\n📰 *Third post*"), "{:?}", calls[0].text());
+
+    // a custom name wins over the feed title
+    let subscription = harness.subscriptions.set_display_name(USER, subscription.id, Some("Blog".to_string())).await.unwrap();
+    assert_eq!(subscription.name(), "Blog");
+    let subscription = harness.subscriptions.set_display_name(USER, subscription.id, None).await.unwrap();
+    assert_eq!(subscription.name(), "Example Blog");
+}
+
+#[tokio::test]
+async fn future_dated_items_wait_without_skipping_earlier_ones() {
+    let harness = Harness::new().await;
+    let future = (chrono::Utc::now() + chrono::Duration::days(365)).to_rfc2822();
+    harness.feeds.serve("/blog.xml", feed(&[("scheduled", &future), ("old", "Tue, 04 Jun 2024 12:00:00 GMT")]));
+    let subscription = subscribe(&harness, "/blog.xml", 2).await;
+
+    harness.sync().await;
+    let calls = harness.deliver(1).await;
+    assert!(calls[0].text().unwrap().contains("old"));
+    let old = chrono::NaiveDate::from_ymd_opt(2024, 6, 4).unwrap().and_hms_opt(12, 0, 0).unwrap();
+    assert_eq!(reload(&harness, &subscription).await.last_updated, old);
+
+    // published after the last sync, dated before the scheduled item
+    harness.feeds.serve("/blog.xml", feed(&[
+        ("scheduled", &future), ("late", "Mon, 10 Jun 2024 12:00:00 GMT"), ("old", "Tue, 04 Jun 2024 12:00:00 GMT"),
+    ]));
+    harness.sync().await;
+    let calls = harness.deliver(2).await;
+    assert!(calls[1].text().unwrap().contains("late"));
+    assert_eq!(deliveries(&harness).await.len(), 2);
+}
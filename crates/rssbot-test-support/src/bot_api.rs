@@ -0,0 +1,254 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::header::CONTENT_TYPE;
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::post;
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use teloxide::types::Me;
+use teloxide::Bot;
+
+use crate::server::LocalServer;
+
+/// Token of the bot talking to the mock, any well-formed token would do.
+pub const TOKEN: &str = "123456:TEST-TOKEN";
+/// Username the mock reports in `getMe`.
+pub const BOT_USERNAME: &str = "test_bot";
+
+/// A Bot API method call received by the mock.
+#[derive(Debug, Clone)]
+pub struct Call {
+    /// Method name as sent by the client, e.g. `SendMessage`.
+    pub method: String,
+    /// JSON parameters, `null` for requests without a JSON body.
+    pub params: Value,
+}
+
+impl Call {
+    pub fn is(&self, method: &str) -> bool {
+        self.method.eq_ignore_ascii_case(method)
+    }
+
+    /// The `chat_id` parameter, when it is numeric.
+    pub fn chat_id(&self) -> Option<i64> {
+        self.params.get("chat_id").and_then(Value::as_i64)
+    }
+
+    pub fn text(&self) -> Option<&str> {
+        self.params.get("text").and_then(Value::as_str)
+    }
+}
+
+/// A scripted response to the next call of a method.
+#[derive(Debug, Clone)]
+pub enum Reply {
+    /// Succeed with the given `result`.
+    Ok(Value),
+    /// Fail with `429 Too Many Requests` and `retry_after`.
+    RetryAfter(u32),
+    /// Fail with `400 Bad Request` and `migrate_to_chat_id`.
+    MigrateToChatId(i64),
+    /// Fail with an arbitrary error.
+    Error { code: u16, description: String },
+}
+
+impl Reply {
+    /// The error returned for chats whose user blocked the bot.
+    pub fn blocked() -> Self {
+        Reply::Error { code: 403, description: "Forbidden: bot was blocked by the user".to_string() }
+    }
+}
+
+#[derive(Default)]
+struct Recorder {
+    calls: Mutex<Vec<Call>>,
+    /// Scripted replies by lowercase method name, consumed in order.
+    scripted: Mutex<HashMap<String, VecDeque<Reply>>>,
+    next_message_id: AtomicI32,
+}
+
+/// A fake Telegram Bot API recording every call.
+///
+/// Calls succeed with plausible results unless a reply was scripted with [`MockBotApi::reply_next`].
+pub struct MockBotApi {
+    server: LocalServer,
+    recorder: Arc<Recorder>,
+}
+
+impl MockBotApi {
+    pub fn start() -> Self {
+        let recorder = Arc::new(Recorder::default());
+        let router = Router::new()
+            .route("/:token/:method", post(handle))
+            .with_state(recorder.clone());
+
+        Self { server: LocalServer::start(router), recorder }
+    }
+
+    /// A bot sending its requests to this mock.
+    pub fn bot(&self) -> Bot {
+        let url = format!("http://{}/", self.server.address()).parse().expect("Invalid mock URL.");
+        Bot::new(TOKEN).set_api_url(url)
+    }
+
+    /// What `getMe` returns, as injected by the dispatcher.
+    pub fn me(&self) -> Me {
+        serde_json::from_value(me()).expect("Invalid `getMe` result.")
+    }
+
+    /// Script the reply to the next call of `method`, replies queue up in order.
+    pub fn reply_next(&self, method: &str, reply: Reply) {
+        self.recorder.scripted.lock().unwrap()
+            .entry(method.to_ascii_lowercase())
+            .or_default()
+            .push_back(reply);
+    }
+
+    /// Every call received so far.
+    pub fn calls(&self) -> Vec<Call> {
+        self.recorder.calls.lock().unwrap().clone()
+    }
+
+    /// Calls of `method` received so far.
+    pub fn calls_to(&self, method: &str) -> Vec<Call> {
+        self.calls().into_iter().filter(|call| call.is(method)).collect()
+    }
+
+    /// Wait until `count` calls of `method` were received, panicking after `timeout`.
+    pub async fn wait_for_calls(&self, method: &str, count: usize, timeout: Duration) -> Vec<Call> {
+        let wait = async {
+            loop {
+                let calls = self.calls_to(method);
+                if calls.len() >= count {
+                    return calls;
+                }
+
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+
+        match tokio::time::timeout(timeout, wait).await {
+            Ok(calls) => calls,
+            Err(_) => panic!("Expected {} calls of `{}` within {:?}, got: {:#?}", count, method, timeout, self.calls()),
+        }
+    }
+}
+
+async fn handle(
+    State(recorder): State<Arc<Recorder>>,
+    Path((_token, method)): Path<(String, String)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> (StatusCode, Json<Value>) {
+    let params = params(&headers, &body);
+    let call = Call { method: method.clone(), params };
+
+    let scripted = recorder.scripted.lock().unwrap()
+        .get_mut(&method.to_ascii_lowercase())
+        .and_then(VecDeque::pop_front);
+    let reply = scripted.unwrap_or_else(|| Reply::Ok(default_result(&recorder, &call)));
+
+    recorder.calls.lock().unwrap().push(call);
+
+    match reply {
+        Reply::Ok(result) => (StatusCode::OK, Json(json!({ "ok": true, "result": result }))),
+        Reply::RetryAfter(seconds) => (
+            StatusCode::TOO_MANY_REQUESTS,
+            Json(json!({
+                "ok": false,
+                "error_code": 429,
+                "description": format!("Too Many Requests: retry after {}", seconds),
+                "parameters": { "retry_after": seconds },
+            })),
+        ),
+        Reply::MigrateToChatId(chat_id) => (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "ok": false,
+                "error_code": 400,
+                "description": "Bad Request: group chat was upgraded to a supergroup chat",
+                "parameters": { "migrate_to_chat_id": chat_id },
+            })),
+        ),
+        Reply::Error { code, description } => (
+            StatusCode::from_u16(code).unwrap_or(StatusCode::BAD_REQUEST),
+            Json(json!({ "ok": false, "error_code": code, "description": description })),
+        ),
+    }
+}
+
+/// Parameters of a call, sent as JSON or, by methods taking files, as a multipart form.
+fn params(headers: &HeaderMap, body: &[u8]) -> Value {
+    let boundary = headers.get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| value.starts_with("multipart/form-data"))
+        .and_then(|value| value.split_once("boundary="))
+        .map(|(_, boundary)| boundary.trim_matches('"'));
+
+    match boundary {
+        Some(boundary) => form_fields(&String::from_utf8_lossy(body), boundary),
+        None => serde_json::from_slice(body).unwrap_or(Value::Null),
+    }
+}
+
+/// Text fields of a multipart form as strings, files are left out.
+fn form_fields(body: &str, boundary: &str) -> Value {
+    let fields = body.split(&format!("--{}", boundary))
+        .filter_map(|part| {
+            let (head, value) = part.split_once("\r\n\r\n")?;
+            if head.contains("filename=") {
+                return None;
+            }
+
+            let name = head.split("name=\"").nth(1)?.split('"').next()?;
+            Some((name.to_string(), Value::String(value.strip_suffix("\r\n").unwrap_or(value).to_string())))
+        })
+        .collect();
+
+    Value::Object(fields)
+}
+
+fn default_result(recorder: &Recorder, call: &Call) -> Value {
+    match call.method.to_ascii_lowercase().as_str() {
+        "getme" => me(),
+        "sendmessage" => {
+            let message_id = recorder.next_message_id.fetch_add(1, Ordering::Relaxed) + 1;
+            let chat_id = call.chat_id().unwrap_or_default();
+
+            json!({
+                "message_id": message_id,
+                "date": 0,
+                "chat": chat(chat_id),
+                "from": me(),
+                "text": call.text().unwrap_or_default(),
+            })
+        }
+        _ => json!(true),
+    }
+}
+
+fn me() -> Value {
+    json!({
+        "id": 123456,
+        "is_bot": true,
+        "first_name": "Test Bot",
+        "username": BOT_USERNAME,
+        "can_join_groups": true,
+        "can_read_all_group_messages": false,
+        "supports_inline_queries": false,
+    })
+}
+
+/// A private chat for positive IDs, a supergroup otherwise.
+pub(crate) fn chat(chat_id: i64) -> Value {
+    if chat_id < 0 {
+        json!({ "id": chat_id, "type": "supergroup", "title": "Test Group" })
+    } else {
+        json!({ "id": chat_id, "type": "private", "first_name": "Test", "username": format!("user{}", chat_id) })
+    }
+}
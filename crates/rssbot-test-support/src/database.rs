@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+
+use sea_orm::{Database, DatabaseConnection};
+
+/// A migrated SQLite database in a temporary file, deleted when dropped.
+///
+/// A file rather than `:memory:`, so that every connection of the pool sees the same data
+/// and concurrent transactions wait for each other instead of failing.
+pub struct TestDatabase {
+    db: DatabaseConnection,
+    path: PathBuf,
+}
+
+impl TestDatabase {
+    pub async fn new() -> Self {
+        let path = std::env::temp_dir().join(format!("rssbot-test-{}.db", uuid::Uuid::new_v4()));
+        let db = Database::connect(format!("sqlite://{}?mode=rwc", path.display()))
+            .await
+            .expect("Failed to open the test database.");
+
+        rssbot_migrator::schema::migrate(&db).await.expect("Failed to migrate the test database.");
+
+        Self { db, path }
+    }
+
+    pub fn connection(&self) -> DatabaseConnection {
+        self.db.clone()
+    }
+}
+
+impl Drop for TestDatabase {
+    fn drop(&mut self) {
+        for suffix in ["", "-journal", "-wal", "-shm"] {
+            let mut path = self.path.clone().into_os_string();
+            path.push(suffix);
+            std::fs::remove_file(path).ok();
+        }
+    }
+}
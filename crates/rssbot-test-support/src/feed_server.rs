@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::body::Body;
+use axum::http::{header, HeaderMap, Response, StatusCode, Uri};
+use axum::Router;
+
+use crate::server::LocalServer;
+
+/// A response served by [`FeedServer`].
+#[derive(Debug, Clone)]
+pub struct Fixture {
+    pub status: u16,
+    pub content_type: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// How long to wait before responding.
+    pub delay: Duration,
+}
+
+impl Fixture {
+    /// An RSS document.
+    pub fn rss(body: impl Into<Vec<u8>>) -> Self {
+        Self { status: 200, content_type: "application/rss+xml".to_string(), headers: Vec::new(), body: body.into(), delay: Duration::ZERO }
+    }
+
+    /// An HTML page, e.g. an article for full-text extraction.
+    pub fn html(body: impl Into<Vec<u8>>) -> Self {
+        Self { status: 200, content_type: "text/html; charset=utf-8".to_string(), headers: Vec::new(), body: body.into(), delay: Duration::ZERO }
+    }
+
+    /// A file of the `fixtures` directory, with a content type guessed from its extension.
+    pub fn file(name: &str) -> Self {
+        let path = fixture_path(name);
+        let body = std::fs::read(&path).unwrap_or_else(|err| panic!("Failed to read fixture {}: {}", path.display(), err));
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("html") => Self::html(body),
+            Some("json") => Self { content_type: "application/feed+json".to_string(), ..Self::rss(body) },
+            _ => Self::rss(body),
+        }
+    }
+
+    /// An empty response with the given status.
+    pub fn status(status: u16) -> Self {
+        Self { status, content_type: "text/plain".to_string(), headers: Vec::new(), body: Vec::new(), delay: Duration::ZERO }
+    }
+
+    /// A `302 Found` redirect to `location`.
+    pub fn redirect(location: &str) -> Self {
+        Self::status(302).with_header("Location", location)
+    }
+
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn with_status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+}
+
+/// Path of a file in the `fixtures` directory of this crate.
+pub fn fixture_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(name)
+}
+
+#[derive(Default)]
+struct Routes {
+    fixtures: Mutex<HashMap<String, Fixture>>,
+    hits: Mutex<HashMap<String, usize>>,
+    headers: Mutex<HashMap<String, HeaderMap>>,
+}
+
+/// An HTTP server for feed fixtures, answering `404` for paths without one.
+pub struct FeedServer {
+    server: LocalServer,
+    routes: Arc<Routes>,
+}
+
+impl FeedServer {
+    pub fn start() -> Self {
+        let routes = Arc::new(Routes::default());
+        let router = Router::new()
+            .fallback(handle)
+            .with_state(routes.clone());
+
+        Self { server: LocalServer::start(router), routes }
+    }
+
+    /// Serve `fixture` at `path`, replacing what was served there before.
+    pub fn serve(&self, path: &str, fixture: Fixture) {
+        self.routes.fixtures.lock().unwrap().insert(path.to_string(), fixture);
+    }
+
+    /// Absolute URL of `path`.
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.server.address(), path)
+    }
+
+    /// Number of requests received for `path`.
+    pub fn hits(&self, path: &str) -> usize {
+        self.routes.hits.lock().unwrap().get(path).copied().unwrap_or_default()
+    }
+
+    /// Headers of the last request received for `path`.
+    pub fn last_headers(&self, path: &str) -> Option<HeaderMap> {
+        self.routes.headers.lock().unwrap().get(path).cloned()
+    }
+}
+
+async fn handle(State(routes): State<Arc<Routes>>, uri: Uri, headers: HeaderMap) -> Response<Body> {
+    *routes.hits.lock().unwrap().entry(uri.path().to_string()).or_default() += 1;
+    routes.headers.lock().unwrap().insert(uri.path().to_string(), headers);
+
+    let fixture = routes.fixtures.lock().unwrap().get(uri.path()).cloned();
+    let fixture = fixture.unwrap_or_else(|| Fixture::status(404));
+    tokio::time::sleep(fixture.delay).await;
+
+    let mut response = Response::builder()
+        .status(StatusCode::from_u16(fixture.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR))
+        .header(header::CONTENT_TYPE, fixture.content_type);
+    for (name, value) in fixture.headers {
+        response = response.header(name, value);
+    }
+
+    response.body(Body::from(fixture.body)).expect("Invalid fixture headers.")
+}
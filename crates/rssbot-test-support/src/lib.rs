@@ -0,0 +1,13 @@
+//! Test doubles for running the bot end-to-end without external services: a fake Telegram
+//! Bot API, an HTTP server for feed fixtures and a throwaway SQLite database.
+
+pub mod bot_api;
+pub mod database;
+pub mod feed_server;
+pub mod updates;
+
+mod server;
+
+pub use bot_api::{Call, MockBotApi, Reply};
+pub use database::TestDatabase;
+pub use feed_server::{FeedServer, Fixture};
@@ -0,0 +1,41 @@
+use std::net::{SocketAddr, TcpListener};
+
+use axum::Router;
+use tokio::sync::oneshot;
+
+/// An HTTP server on a random local port, stopped when dropped.
+pub struct LocalServer {
+    address: SocketAddr,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl LocalServer {
+    /// Serve `router` in the background of the current runtime.
+    pub fn start(router: Router) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind a local port.");
+        let address = listener.local_addr().expect("Failed to read the local address.");
+        let (shutdown, stopped) = oneshot::channel::<()>();
+
+        let server = axum::Server::from_tcp(listener)
+            .expect("Failed to serve on the local port.")
+            .serve(router.into_make_service())
+            .with_graceful_shutdown(async {
+                stopped.await.ok();
+            });
+        tokio::spawn(server);
+
+        Self { address, shutdown: Some(shutdown) }
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+impl Drop for LocalServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.send(()).ok();
+        }
+    }
+}
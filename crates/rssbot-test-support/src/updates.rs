@@ -0,0 +1,71 @@
+//! Updates as Telegram would send them, to feed into the handler tree.
+
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use serde_json::{json, Value};
+use teloxide::types::Update;
+
+use crate::bot_api::{chat, BOT_USERNAME};
+
+static NEXT_ID: AtomicI32 = AtomicI32::new(1);
+
+fn next_id() -> i32 {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// A text message from `user_id` in its private chat with the bot.
+pub fn private_message(user_id: i64, text: &str) -> Update {
+    message(user_id, user_id, text)
+}
+
+/// A text message from `user_id` in the group `chat_id`, which must be negative.
+pub fn group_message(chat_id: i64, user_id: i64, text: &str) -> Update {
+    message(chat_id, user_id, text)
+}
+
+/// A press by `user_id` on an inline button carrying `data`, under a message of the bot in
+/// their private chat.
+pub fn callback_query(user_id: i64, data: &str) -> Update {
+    update(json!({
+        "update_id": next_id(),
+        "callback_query": {
+            "id": format!("query-{}", next_id()),
+            "from": user(user_id),
+            "chat_instance": "0",
+            "data": data,
+            "message": {
+                "message_id": next_id(),
+                "date": 0,
+                "chat": chat(user_id),
+                "from": { "id": 123456, "is_bot": true, "first_name": "Test Bot", "username": BOT_USERNAME },
+                "text": "Choose an option",
+            },
+        },
+    }))
+}
+
+fn message(chat_id: i64, user_id: i64, text: &str) -> Update {
+    let mut message = json!({
+        "message_id": next_id(),
+        "date": 0,
+        "chat": chat(chat_id),
+        "from": user(user_id),
+        "text": text,
+    });
+
+    if text.starts_with('/') {
+        let length = text.split_whitespace().next().unwrap_or_default().encode_utf16().count();
+        message["entities"] = json!([{ "type": "bot_command", "offset": 0, "length": length }]);
+    }
+
+    update(json!({ "update_id": next_id(), "message": message }))
+}
+
+fn user(user_id: i64) -> Value {
+    json!({ "id": user_id, "is_bot": false, "first_name": "Test", "username": format!("user{}", user_id) })
+}
+
+fn update(value: Value) -> Update {
+    // `Update` silently turns into `UpdateKind::Error` when deserialized from a `Value`
+    serde_json::from_str(&value.to_string()).expect("Invalid update.")
+}